@@ -0,0 +1,21 @@
+//! `tetris-tbp [--seed N] [--pieces N] <bot command>...`
+//! Runs bots speaking the Tetris Bot Protocol through the same seeded game.
+
+// the shared modules re-export more than this binary needs
+#![allow(dead_code, unused_imports)]
+#![feature(array_chunks, slice_pattern)]
+
+#[path = "../bot/mod.rs"]
+mod bot;
+#[path = "../engine/mod.rs"]
+mod engine;
+#[path = "../json.rs"]
+mod json;
+#[path = "../tbp/mod.rs"]
+mod tbp;
+
+
+fn main() {
+    let args = std::env::args().collect::<Vec<_>>();
+    tbp::frontend::run(&args[1..]);
+}
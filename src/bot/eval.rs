@@ -0,0 +1,264 @@
+use std::{fmt, str::FromStr};
+use crate::engine::{Coordinate, Matrix};
+
+/// Weights of the board evaluation, positive is good.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Weights {
+    pub aggregate_height: f32,
+    pub holes: f32,
+    pub bumpiness: f32,
+    pub wells: f32,
+    pub row_transitions: f32,
+    pub column_transitions: f32,
+    pub tslots: f32,
+    /// Reward for clearing 1, 2, 3 and 4 lines at once.
+    pub clears: [f32; 4],
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Weights {
+            aggregate_height: -0.5,
+            holes: -4.0,
+            bumpiness: -0.3,
+            wells: -0.3,
+            row_transitions: -0.8,
+            column_transitions: -1.0,
+            tslots: 1.0,
+            clears: [-1.0, -0.5, 0.5, 6.0],
+        }
+    }
+}
+
+impl Weights {
+    pub const COUNT: usize = 11;
+    /// Names used in weight files, in `to_array` order.
+    pub const NAMES: [&'static str; Self::COUNT] = [
+        "aggregate_height",
+        "holes",
+        "bumpiness",
+        "wells",
+        "row_transitions",
+        "column_transitions",
+        "tslots",
+        "clear_single",
+        "clear_double",
+        "clear_triple",
+        "clear_tetris",
+    ];
+
+    pub fn to_array(self) -> [f32; Self::COUNT] {
+        let [single, double, triple, tetris] = self.clears;
+        [
+            self.aggregate_height,
+            self.holes,
+            self.bumpiness,
+            self.wells,
+            self.row_transitions,
+            self.column_transitions,
+            self.tslots,
+            single,
+            double,
+            triple,
+            tetris,
+        ]
+    }
+
+    pub fn from_array(values: [f32; Self::COUNT]) -> Self {
+        let [
+            aggregate_height,
+            holes,
+            bumpiness,
+            wells,
+            row_transitions,
+            column_transitions,
+            tslots,
+            clears @ ..
+        ] = values;
+        Weights {
+            aggregate_height,
+            holes,
+            bumpiness,
+            wells,
+            row_transitions,
+            column_transitions,
+            tslots,
+            clears,
+        }
+    }
+
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self, String> {
+        std::fs::read_to_string(path)
+            .map_err(|error| error.to_string())?
+            .parse()
+    }
+
+    pub fn evaluate(&self, matrix: &Matrix) -> f32 {
+        let features = Features::of(matrix);
+
+        self.aggregate_height * features.aggregate_height
+            + self.holes * features.holes
+            + self.bumpiness * features.bumpiness
+            + self.wells * features.wells
+            + self.row_transitions * features.row_transitions
+            + self.column_transitions * features.column_transitions
+            + self.tslots * features.tslots
+    }
+
+    pub fn clear_reward(&self, lines: usize) -> f32 {
+        match lines {
+            0 => 0.0,
+            lines => self.clears[lines.min(4) - 1],
+        }
+    }
+}
+
+
+/// One `name = value` line per weight, `#` starts a comment.
+impl fmt::Display for Weights {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, value) in Self::NAMES.iter().zip(self.to_array()) {
+            writeln!(f, "{name} = {value}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Weights missing from the text keep their default value.
+impl FromStr for Weights {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut values = Weights::default().to_array();
+
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (name, value) = line.split_once('=').ok_or_else(|| format!("expected `name = value`, got `{line}`"))?;
+            let index = Self::NAMES.iter()
+                .position(|known| *known == name.trim())
+                .ok_or_else(|| format!("unknown weight `{}`", name.trim()))?;
+            values[index] = value.trim().parse().map_err(|_| format!("bad value for `{}`", name.trim()))?;
+        }
+        Ok(Self::from_array(values))
+    }
+}
+
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Features {
+    pub aggregate_height: f32,
+    pub holes: f32,
+    pub bumpiness: f32,
+    pub wells: f32,
+    pub row_transitions: f32,
+    pub column_transitions: f32,
+    pub tslots: f32,
+}
+
+impl Features {
+    pub fn of(matrix: &Matrix) -> Self {
+        let board = Board(matrix);
+        let heights: [usize; Matrix::WIDTH] = std::array::from_fn(|x| board.height(x));
+        let stack_height = heights.iter().copied().max().unwrap_or(0);
+
+        let mut features = Features {
+            aggregate_height: heights.iter().sum::<usize>() as f32,
+            ..Default::default()
+        };
+
+        for pair in heights.windows(2) {
+            features.bumpiness += pair[0].abs_diff(pair[1]) as f32;
+        }
+
+        for x in 0..Matrix::WIDTH {
+            let left = if x == 0 { Matrix::HEIGHT } else { heights[x - 1] };
+            let right = heights.get(x + 1).copied().unwrap_or(Matrix::HEIGHT);
+            features.wells += left.min(right).saturating_sub(heights[x]) as f32;
+
+            let mut below_filled = true;
+            for y in 0..Matrix::HEIGHT {
+                let filled = board.filled(x as isize, y as isize);
+                if y < heights[x] && !filled {
+                    features.holes += 1.0;
+                }
+                if filled != below_filled {
+                    features.column_transitions += 1.0;
+                }
+                below_filled = filled;
+            }
+        }
+
+        for y in 0..stack_height as isize {
+            let mut last = true;
+            for x in 0..=Matrix::WIDTH as isize {
+                let filled = board.filled(x, y);
+                if filled != last {
+                    features.row_transitions += 1.0;
+                }
+                last = filled;
+            }
+        }
+
+        for x in 1..Matrix::WIDTH as isize - 1 {
+            for y in 0..stack_height as isize {
+                if board.is_tslot(x, y) {
+                    features.tslots += 1.0;
+                }
+            }
+        }
+
+        features
+    }
+}
+
+
+/// Read-only view treating walls and floor as filled.
+struct Board<'matrix>(&'matrix Matrix);
+
+impl Board<'_> {
+    fn filled(&self, x: isize, y: isize) -> bool {
+        if x < 0 || y < 0 || x >= Matrix::WIDTH as isize {
+            return true;
+        }
+        if y >= Matrix::HEIGHT as isize {
+            return false;
+        }
+        self.0[Coordinate::new(x as usize, y as usize)].is_some()
+    }
+
+    fn height(&self, x: usize) -> usize {
+        (0..Matrix::HEIGHT)
+            .rev()
+            .find(|&y| self.filled(x as isize, y as isize))
+            .map_or(0, |y| y + 1)
+    }
+
+    /// Slot for a downward pointing T with its stem at `(x, y)`:
+    /// open row above the stem and one overhanging corner.
+    fn is_tslot(&self, x: isize, y: isize) -> bool {
+        let stem = !self.filled(x, y) && self.filled(x - 1, y) && self.filled(x + 1, y);
+        let arms = (x - 1..=x + 1).all(|x| !self.filled(x, y + 1));
+        let overhangs = [self.filled(x - 1, y + 2), self.filled(x + 1, y + 2)];
+
+        stem && arms && overhangs[0] != overhangs[1]
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn weights_file_round_trip() {
+        let weights = Weights { holes: -7.25, clears: [0.0, 1.0, 2.0, 3.5], ..Weights::default() };
+        assert_eq!(weights.to_string().parse::<Weights>(), Ok(weights));
+
+        let partial = "# tuned\nholes = -2\n".parse::<Weights>().unwrap();
+        assert_eq!(partial, Weights { holes: -2.0, ..Weights::default() });
+        assert!("height = 1".parse::<Weights>().is_err());
+    }
+}
@@ -0,0 +1,225 @@
+use crate::engine::{Engine, Matrix, MoveKind, piece::{Piece, Kind as PieceKind, RotateKind}};
+
+pub use self::eval::Weights;
+pub use self::movegen::placements;
+pub use self::opponent::{Difficulty, Opponent};
+
+mod eval;
+mod movegen;
+mod opponent;
+pub mod perfect_clear;
+pub mod tune;
+
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Action {
+    Move(MoveKind),
+    Rotate(RotateKind),
+    /// Soft drop all the way down, used for tucks and spins.
+    SonicDrop,
+    HardDrop,
+    Hold,
+}
+
+impl Action {
+    pub fn apply(&self, engine: &mut Engine) -> Result<(), ()> {
+        match *self {
+            Action::Move(kind) => engine.move_cursor(kind),
+            Action::Rotate(kind) => engine.rotate_cursor(kind),
+            Action::SonicDrop => {
+                while engine.soft_drop() {}
+                Ok(())
+            },
+            Action::HardDrop => {
+                engine.hard_drop();
+                Ok(())
+            },
+            Action::Hold => engine.hold(),
+        }
+    }
+}
+
+
+/// Inputs for one piece, ending with a hard drop.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Plan {
+    pub actions: Vec<Action>,
+    pub target: Piece,
+}
+
+
+#[derive(Clone, Debug)]
+pub struct Bot {
+    pub weights: Weights,
+    /// How many queued pieces are searched beyond the current one.
+    pub lookahead: usize,
+    pub use_hold: bool,
+}
+
+impl Default for Bot {
+    fn default() -> Self {
+        Bot {
+            weights: Weights::default(),
+            lookahead: 1,
+            use_hold: true,
+        }
+    }
+}
+
+/// A piece to place, along with the hold and queue left once it is chosen.
+struct Candidate<'queue> {
+    start: Piece,
+    held: bool,
+    hold: Option<PieceKind>,
+    queue: &'queue [PieceKind],
+}
+
+/// Playing the current piece, and holding it when allowed.
+/// Holding into an empty hold takes the next queued piece instead.
+fn candidates(
+    current: Piece,
+    hold: Option<PieceKind>,
+    queue: &[PieceKind],
+    can_hold: bool,
+) -> Vec<Candidate<'_>> {
+    let mut candidates = vec![Candidate { start: current, held: false, hold, queue }];
+    if !can_hold {
+        return candidates;
+    }
+
+    let swapped = match (hold, queue) {
+        (Some(kind), _) => Some((kind, queue)),
+        (None, [next, rest @ ..]) => Some((*next, rest)),
+        (None, []) => None,
+    };
+    if let Some((kind, queue)) = swapped.filter(|(kind, _)| *kind != current.kind) {
+        candidates.push(Candidate {
+            start: Piece::spawned(kind),
+            held: true,
+            hold: Some(current.kind),
+            queue,
+        });
+    }
+    candidates
+}
+
+impl Bot {
+    pub fn new(weights: Weights, lookahead: usize) -> Self {
+        Bot { weights, lookahead, ..Self::default() }
+    }
+
+    pub fn think(&self, engine: &Engine) -> Option<Plan> {
+        let queue = engine.queue().collect::<Vec<_>>();
+        self.suggest(engine.matrix(), engine.cursor()?, engine.held(), &queue, engine.can_hold())
+    }
+
+    /// Same search as `think`, for game states that don't live in an `Engine`.
+    pub fn suggest(
+        &self,
+        matrix: &Matrix,
+        current: Piece,
+        hold: Option<PieceKind>,
+        queue: &[PieceKind],
+        can_hold: bool,
+    ) -> Option<Plan> {
+        let candidates = candidates(current, hold, queue, self.use_hold && can_hold);
+        let mut best: Option<(f32, Plan)> = None;
+
+        for candidate in candidates {
+            for placement in placements(matrix, candidate.start) {
+                let score = self.score(
+                    matrix, &placement.piece, candidate.hold, candidate.queue, self.lookahead
+                );
+                if best.as_ref().is_some_and(|(best, _)| *best >= score) {
+                    continue;
+                }
+
+                let mut actions = Vec::with_capacity(placement.actions.len() + 2);
+                if candidate.held {
+                    actions.push(Action::Hold);
+                }
+                actions.extend(placement.actions);
+                actions.push(Action::HardDrop);
+                best = Some((score, Plan { actions, target: placement.piece }));
+            }
+        }
+        best.map(|(_, plan)| plan)
+    }
+
+    /// Spawns if needed, then plays one piece and returns the lines it cleared.
+    /// Fails once the engine tops out.
+    pub fn play_piece(&self, engine: &mut Engine) -> Result<usize, ()> {
+        if engine.cursor().is_none() {
+            engine.spawn()?;
+        }
+        let plan = self.think(engine).ok_or(())?;
+        for action in plan.actions {
+            action.apply(engine)?;
+        }
+        let mut cleared = 0;
+        engine.line_clear(|lines| cleared = lines.len());
+
+        match engine.is_topped_out() {
+            true => Err(()),
+            false => Ok(cleared),
+        }
+    }
+
+    fn score(
+        &self,
+        matrix: &Matrix,
+        piece: &Piece,
+        hold: Option<PieceKind>,
+        queue: &[PieceKind],
+        depth: usize,
+    ) -> f32 {
+        let mut matrix = *matrix;
+        matrix.place(piece);
+        let lines = matrix.full_lines();
+        matrix.clear_line(&lines);
+
+        let reward = self.weights.clear_reward(lines.len());
+        let Some((next, rest)) = queue.split_first().filter(|_| depth > 0) else {
+            return reward + self.weights.evaluate(&matrix);
+        };
+
+        let best = candidates(Piece::spawned(*next), hold, rest, self.use_hold)
+            .into_iter()
+            .flat_map(|candidate| {
+                placements(&matrix, candidate.start)
+                    .into_iter()
+                    .map(move |placement| (placement.piece, candidate.hold, candidate.queue))
+            })
+            .map(|(piece, hold, queue)| self.score(&matrix, &piece, hold, queue, depth - 1))
+            .fold(f32::NEG_INFINITY, f32::max);
+
+        reward + best
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn placements_on_empty_matrix() {
+        let matrix = Matrix::blank();
+        let count = |kind| placements(&matrix, Piece::spawned(kind)).len();
+
+        assert_eq!(count(PieceKind::T), 34);
+        assert_eq!(count(PieceKind::I), 17);
+        assert_eq!(count(PieceKind::O), 9);
+        assert_eq!(count(PieceKind::S), 17);
+    }
+
+    #[test]
+    fn headless_bot_survives() {
+        let mut engine = Engine::with_seed(7);
+        let bot = Bot { lookahead: 0, ..Bot::default() };
+
+        for _ in 0..200 {
+            bot.play_piece(&mut engine).expect("bot topped out");
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use std::collections::HashSet;
+use crate::engine::{Coordinate, Matrix, MoveKind, Offset, piece::{Piece, RotateKind}};
+use super::Action;
+
+/// A reachable resting spot and the inputs that lead there from the start piece.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Placement {
+    pub piece: Piece,
+    /// Inputs before the final hard drop.
+    pub actions: Vec<Action>,
+}
+
+struct Node {
+    piece: Piece,
+    parent: Option<(usize, Action)>,
+}
+
+/// Breadth first search over cursor states, so every placement carries
+/// the shortest input path the bot knows about.
+pub fn placements(matrix: &Matrix, start: Piece) -> Vec<Placement> {
+    if matrix.is_clipping(&start) {
+        return Vec::new();
+    }
+
+    let mut nodes = vec![Node { piece: start, parent: None }];
+    let mut seen = HashSet::from([start]);
+    let mut landed: HashSet<Vec<Coordinate>> = HashSet::new();
+    let mut order = Vec::new();
+
+    let mut index = 0;
+    while index < nodes.len() {
+        let piece = nodes[index].piece;
+
+        let dropped = dropped(matrix, piece);
+        if matrix.placeable(&dropped) {
+            let mut cells = dropped.cells().unwrap();
+            cells.sort_by_key(|coord| (coord.y, coord.x));
+            if landed.insert(cells) {
+                order.push(index);
+            }
+        }
+
+        for (action, next) in successors(matrix, piece) {
+            if seen.insert(next) {
+                nodes.push(Node { piece: next, parent: Some((index, action)) });
+            }
+        }
+        index += 1;
+    }
+
+    order.into_iter()
+        .map(|index| Placement {
+            piece: dropped(matrix, nodes[index].piece),
+            actions: path(&nodes, index),
+        })
+        .collect()
+}
+
+pub fn dropped(matrix: &Matrix, mut piece: Piece) -> Piece {
+    let down = Offset::new(0, -1);
+    while !matrix.is_clipping(&piece.moved_by(down)) {
+        piece = piece.moved_by(down);
+    }
+    piece
+}
+
+fn successors(matrix: &Matrix, piece: Piece) -> impl Iterator<Item = (Action, Piece)> + '_ {
+    let shifts = [MoveKind::Left, MoveKind::Right].map(|kind| {
+        let moved = piece.moved_by(kind.offset());
+        (!matrix.is_clipping(&moved)).then_some((Action::Move(kind), moved))
+    });
+    let turns = [RotateKind::Clockwise, RotateKind::CounterClockwise]
+        .map(|kind| Some((Action::Rotate(kind), matrix.rotated(&piece, kind)?)));
+    let drop = {
+        let landed = dropped(matrix, piece);
+        (landed.position != piece.position).then_some((Action::SonicDrop, landed))
+    };
+
+    shifts.into_iter()
+        .chain(turns)
+        .chain([drop])
+        .flatten()
+}
+
+fn path(nodes: &[Node], mut index: usize) -> Vec<Action> {
+    let mut actions = Vec::new();
+    while let Some((parent, action)) = nodes[index].parent {
+        actions.push(action);
+        index = parent;
+    }
+    actions.reverse();
+    actions
+}
@@ -0,0 +1,96 @@
+use std::{str::FromStr, time::Duration};
+use rand::{prelude::{SliceRandom, StdRng}, Rng, SeedableRng};
+use crate::engine::Engine;
+use super::{Action, Bot, Plan, Weights, placements};
+
+/// How hard a computer opponent plays.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Difficulty {
+    /// Pieces per second the bot is held to.
+    pub pps: f32,
+    /// Search depth, see `Bot::lookahead`.
+    pub lookahead: usize,
+    /// Chance of playing a random placement instead of the best one.
+    pub mistake_rate: f64,
+}
+
+impl Difficulty {
+    pub const EASY: Self = Difficulty { pps: 0.8, lookahead: 0, mistake_rate: 0.15 };
+    pub const MEDIUM: Self = Difficulty { pps: 1.5, lookahead: 0, mistake_rate: 0.04 };
+    pub const HARD: Self = Difficulty { pps: 3.0, lookahead: 1, mistake_rate: 0.0 };
+}
+
+impl Default for Difficulty {
+    fn default() -> Self {
+        Self::MEDIUM
+    }
+}
+
+impl FromStr for Difficulty {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "easy" => Ok(Self::EASY),
+            "medium" => Ok(Self::MEDIUM),
+            "hard" => Ok(Self::HARD),
+            _ => Err(format!("unknown difficulty `{name}`, expected easy, medium or hard")),
+        }
+    }
+}
+
+
+/// A bot playing against people, slowed down and made fallible by its `Difficulty`.
+pub struct Opponent {
+    pub bot: Bot,
+    pub difficulty: Difficulty,
+    rng: StdRng,
+}
+
+impl Opponent {
+    pub fn new(weights: Weights, difficulty: Difficulty) -> Self {
+        Opponent {
+            bot: Bot::new(weights, difficulty.lookahead),
+            difficulty,
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    /// Least time between two pieces.
+    pub fn piece_time(&self) -> Duration {
+        Duration::from_secs_f32(1.0 / self.difficulty.pps.max(0.01))
+    }
+
+    /// Plan for the current piece, now and then a random placement instead of the best.
+    pub fn plan(&mut self, engine: &Engine) -> Option<Plan> {
+        if !self.rng.gen_bool(self.difficulty.mistake_rate.clamp(0.0, 1.0)) {
+            return self.bot.think(engine);
+        }
+        let placement = placements(engine.matrix(), engine.cursor()?).choose(&mut self.rng)?.clone();
+        let mut actions = placement.actions;
+        actions.push(Action::HardDrop);
+        Some(Plan { actions, target: placement.piece })
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn mistakes_are_legal() {
+        let mut engine = Engine::with_seed(11);
+        let mut opponent = Opponent::new(Weights::default(), Difficulty { mistake_rate: 1.0, ..Difficulty::EASY });
+
+        for _ in 0..10 {
+            engine.spawn().unwrap();
+            let plan = opponent.plan(&engine).unwrap();
+            for action in plan.actions {
+                action.apply(&mut engine).unwrap();
+            }
+            assert!(engine.line_clear(|_| ()).is_some());
+        }
+        assert_eq!("hard".parse(), Ok(Difficulty::HARD));
+    }
+}
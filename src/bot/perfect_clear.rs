@@ -0,0 +1,199 @@
+//! Perfect clear search: place pieces so every row up to some height fills and clears.
+
+use std::collections::HashSet;
+use crate::engine::{Coordinate, Matrix, piece::{Piece, Kind as PieceKind}};
+use super::{candidates, placements};
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Step {
+    /// Hold is pressed before placing `piece`.
+    pub held: bool,
+    pub piece: Piece,
+}
+
+/// Finds placements that empty the matrix without stacking above `max_height` rows.
+/// Pieces are drawn from `current`, `hold` and `queue` like in play, so the
+/// first step is what to do with the piece in hand. The parity pruning
+/// counts in tetrominoes, so other piece sets get no solution. The search
+/// gives up after trying `budget` placements, hard positions can take long.
+pub fn solve(
+    matrix: &Matrix,
+    current: PieceKind,
+    hold: Option<PieceKind>,
+    queue: &[PieceKind],
+    max_height: usize,
+    budget: usize,
+) -> Option<Vec<Step>> {
+    let kinds = std::iter::once(current).chain(hold).chain(queue.iter().copied());
+    if kinds.map(|kind| kind.cells().len()).any(|count| count != Piece::CELL_COUNT) {
+        return None;
+    }
+
+    let filled = (0..Matrix::HEIGHT)
+        .flat_map(|y| (0..Matrix::WIDTH).map(move |x| Coordinate::new(x, y)))
+        .filter(|coord| matrix[*coord].is_some())
+        .collect::<Vec<_>>();
+    let stack_height = filled.iter().map(|coord| coord.y + 1).max().unwrap_or(0);
+    let available = 1 + queue.len() + usize::from(hold.is_some());
+    let mut budget = budget;
+
+    for height in stack_height.max(1)..=max_height.min(Matrix::HEIGHT) {
+        let empty = height * Matrix::WIDTH - filled.len();
+        if !empty.is_multiple_of(Piece::CELL_COUNT) || empty / Piece::CELL_COUNT > available {
+            continue;
+        }
+
+        let mut search = Search { failed: HashSet::new(), steps: Vec::new(), budget };
+        if search.solve(*matrix, current, hold, queue, height) {
+            return Some(search.steps);
+        }
+        budget = search.budget;
+    }
+    None
+}
+
+
+struct Search {
+    failed: HashSet<(Matrix, PieceKind, Option<PieceKind>, usize)>,
+    steps: Vec<Step>,
+    /// Placements left to try.
+    budget: usize,
+}
+
+impl Search {
+    fn solve(
+        &mut self,
+        matrix: Matrix,
+        current: PieceKind,
+        hold: Option<PieceKind>,
+        queue: &[PieceKind],
+        height: usize,
+    ) -> bool {
+        if self.failed.contains(&(matrix, current, hold, queue.len())) {
+            return false;
+        }
+
+        for candidate in candidates(Piece::spawned(current), hold, queue, true) {
+            for placement in placements(&matrix, candidate.start) {
+                if self.budget == 0 {
+                    return false;
+                }
+                self.budget -= 1;
+                if placement.piece.cells().unwrap().iter().any(|coord| coord.y >= height) {
+                    continue;
+                }
+
+                let mut next = matrix;
+                next.place(&placement.piece);
+                let lines = next.full_lines();
+                next.clear_line(&lines);
+                let height = height - lines.len();
+
+                if !fields_fillable(&next, height) {
+                    continue;
+                }
+
+                self.steps.push(Step { held: candidate.held, piece: placement.piece });
+                let solved = height == 0 || match candidate.queue.split_first() {
+                    Some((next_kind, rest)) => self.solve(next, *next_kind, candidate.hold, rest, height),
+                    None => false,
+                };
+                if solved {
+                    return true;
+                }
+                self.steps.pop();
+            }
+        }
+
+        self.failed.insert((matrix, current, hold, queue.len()));
+        false
+    }
+}
+
+/// Parity pruning: each enclosed empty region below `height` must take whole pieces.
+/// Regions can merge once the row between them clears, so the odd exotic
+/// solution is pruned along with the impossible ones.
+fn fields_fillable(matrix: &Matrix, height: usize) -> bool {
+    let mut visited = [[false; Matrix::WIDTH]; Matrix::HEIGHT];
+    let empty = |x: usize, y: usize| y < height && matrix[Coordinate::new(x, y)].is_none();
+
+    for y in 0..height {
+        for x in 0..Matrix::WIDTH {
+            if visited[y][x] || !empty(x, y) {
+                continue;
+            }
+
+            let mut size = 0usize;
+            let mut stack = vec![(x, y)];
+            visited[y][x] = true;
+            while let Some((x, y)) = stack.pop() {
+                size += 1;
+                let neighbours = [
+                    x.checked_sub(1).map(|x| (x, y)),
+                    (x + 1 < Matrix::WIDTH).then_some((x + 1, y)),
+                    y.checked_sub(1).map(|y| (x, y)),
+                    Some((x, y + 1)),
+                ];
+                for (x, y) in neighbours.into_iter().flatten() {
+                    if empty(x, y) && !visited[y][x] {
+                        visited[y][x] = true;
+                        stack.push((x, y));
+                    }
+                }
+            }
+
+            if !size.is_multiple_of(Piece::CELL_COUNT) {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn well_matrix() -> Matrix {
+        "
+            LLLLLL....
+            LLLLLL....
+            LLLLLL....
+            LLLLLL....
+        ".parse().unwrap()
+    }
+
+    #[test]
+    fn fills_well() {
+        let steps = solve(&well_matrix(), PieceKind::I, None, &[PieceKind::I; 3], 4, usize::MAX).unwrap();
+
+        let mut matrix = well_matrix();
+        for step in &steps {
+            matrix.place(&step.piece);
+            let lines = matrix.full_lines();
+            matrix.clear_line(&lines);
+        }
+        assert_eq!(steps.len(), 4);
+        assert_eq!(matrix, Matrix::blank());
+    }
+
+    #[test]
+    fn holds_unusable_piece() {
+        let steps = solve(&well_matrix(), PieceKind::T, None, &[PieceKind::O; 4], 4, usize::MAX).unwrap();
+        assert!(steps[0].held);
+        assert_eq!(steps[0].piece.kind, PieceKind::O);
+    }
+
+    #[test]
+    fn gives_up_past_budget() {
+        assert_eq!(solve(&well_matrix(), PieceKind::I, None, &[PieceKind::I; 3], 4, 2), None);
+    }
+
+    #[test]
+    fn odd_cell_count_is_unsolvable() {
+        let matrix = "Z.........".parse().unwrap();
+        assert_eq!(solve(&matrix, PieceKind::I, None, &PieceKind::ALL, 4, usize::MAX), None);
+    }
+}
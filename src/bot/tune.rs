@@ -0,0 +1,172 @@
+//! Evolves evaluation `Weights` with a plain genetic algorithm over headless games.
+
+use std::{fs, thread};
+use rand::{Rng, SeedableRng, prelude::{SliceRandom, StdRng}};
+use crate::engine::Engine;
+use super::{Bot, Weights};
+
+
+#[derive(Clone, Debug)]
+pub struct Settings {
+    pub population: usize,
+    pub generations: usize,
+    /// Seeded games every candidate plays per generation.
+    pub games: usize,
+    /// Games stop here even if the bot is still alive.
+    pub pieces: usize,
+    pub mutation_rate: f64,
+    pub seed: u64,
+    pub output: String,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            population: 24,
+            generations: 20,
+            games: 4,
+            pieces: 500,
+            mutation_rate: 0.2,
+            seed: 0,
+            output: "weights.txt".to_owned(),
+        }
+    }
+}
+
+impl Settings {
+    /// `[--population N] [--generations N] [--games N] [--pieces N] [--seed N] [--output FILE]`
+    pub fn from_args(args: &[String]) -> Self {
+        let mut settings = Settings::default();
+
+        let mut args = args.iter();
+        while let Some(flag) = args.next() {
+            let value = args.next().unwrap_or_else(|| panic!("{flag} needs a value"));
+            let number = || value.parse::<usize>().unwrap_or_else(|_| panic!("{flag} needs a number"));
+            match flag.as_str() {
+                "--population" => settings.population = number().max(3),
+                "--generations" => settings.generations = number(),
+                "--games" => settings.games = number().max(1),
+                "--pieces" => settings.pieces = number(),
+                "--seed" => settings.seed = number() as u64,
+                "--output" => settings.output = value.clone(),
+                _ => panic!("unknown option {flag}"),
+            }
+        }
+        settings
+    }
+}
+
+/// Average lines cleared over the given seeds, so survival is rewarded as well.
+pub fn fitness(weights: Weights, seeds: &[u64], pieces: usize) -> f32 {
+    let bot = Bot::new(weights, 0);
+    let total: usize = seeds.iter()
+        .map(|&seed| {
+            let mut engine = Engine::with_seed(seed);
+            (0..pieces).map_while(|_| bot.play_piece(&mut engine).ok()).sum::<usize>()
+        })
+        .sum();
+    total as f32 / seeds.len() as f32
+}
+
+fn rate_all(population: &[Weights], seeds: &[u64], pieces: usize) -> Vec<f32> {
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = population.len().div_ceil(threads).max(1);
+
+    thread::scope(|scope| {
+        let handles = population.chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || {
+                chunk.iter().map(|weights| fitness(*weights, seeds, pieces)).collect::<Vec<_>>()
+            }))
+            .collect::<Vec<_>>();
+        handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect()
+    })
+}
+
+fn mutated(weights: Weights, rate: f64, rng: &mut StdRng) -> Weights {
+    let mut values = weights.to_array();
+    for value in &mut values {
+        if rng.gen_bool(rate) {
+            *value += rng.gen_range(-1.0..1.0) * (value.abs() * 0.5 + 0.2);
+        }
+    }
+    Weights::from_array(values)
+}
+
+/// Uniform crossover between two parents.
+fn crossed(a: Weights, b: Weights, rng: &mut StdRng) -> Weights {
+    let (a, b) = (a.to_array(), b.to_array());
+    Weights::from_array(std::array::from_fn(|i| if rng.gen_bool(0.5) { a[i] } else { b[i] }))
+}
+
+fn tournament(rated: &[(f32, Weights)], rng: &mut StdRng) -> Weights {
+    rated.choose_multiple(rng, 3)
+        .max_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, weights)| *weights)
+        .unwrap()
+}
+
+/// Runs the search, writing the best weights so far to `settings.output`
+/// after each generation. Returns the overall best, if any were written.
+pub fn evolve(settings: &Settings) -> Option<Weights> {
+    let mut rng = StdRng::seed_from_u64(settings.seed);
+    let mut population = vec![Weights::default()];
+    while population.len() < settings.population {
+        population.push(mutated(Weights::default(), 1.0, &mut rng));
+    }
+
+    let mut best: Option<(f32, Weights)> = None;
+    for generation in 0..settings.generations {
+        let seeds = (0..settings.games).map(|_| rng.gen()).collect::<Vec<u64>>();
+        let mut rated = rate_all(&population, &seeds, settings.pieces)
+            .into_iter()
+            .zip(population)
+            .collect::<Vec<_>>();
+        rated.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        if best.is_none_or(|(lines, _)| rated[0].0 > lines) {
+            fs::write(&settings.output, rated[0].1.to_string()).expect("Fail to write weights");
+            best = Some(rated[0]);
+        }
+        let overall = best.map_or(rated[0].0, |(lines, _)| lines);
+        println!("generation {generation}: best {:.1} lines, overall {overall:.1}", rated[0].0);
+
+        let elite = 2.min(rated.len());
+        population = rated[..elite].iter().map(|(_, weights)| *weights).collect();
+        while population.len() < settings.population {
+            let child = crossed(tournament(&rated, &mut rng), tournament(&rated, &mut rng), &mut rng);
+            population.push(mutated(child, settings.mutation_rate, &mut rng));
+        }
+    }
+    best.map(|(_, weights)| weights)
+}
+
+/// `tetris tune`, see `Settings::from_args` for the options.
+pub fn run(args: &[String]) {
+    let settings = Settings::from_args(args);
+    match evolve(&settings) {
+        Some(best) => println!("best weights written to {}:\n{best}", settings.output),
+        None => println!("no generation ran, {} left alone", settings.output),
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn settings_from_args() {
+        let args = ["--pieces", "5", "--games", "0"].map(String::from);
+        let settings = Settings::from_args(&args);
+        assert_eq!((settings.pieces, settings.games), (5, 1));
+        assert_eq!(settings.population, Settings::default().population);
+    }
+
+    #[test]
+    fn no_generations_write_nothing() {
+        let output = std::env::temp_dir().join(format!("tetris-tune-{}.weights", std::process::id()));
+        let settings = Settings { generations: 0, output: output.to_string_lossy().into_owned(), ..Settings::default() };
+        assert_eq!(evolve(&settings), None);
+        assert!(!output.exists());
+    }
+}
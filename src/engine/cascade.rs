@@ -0,0 +1,207 @@
+use std::{str::FromStr, time::Duration};
+use super::{Matrix, remove_rows};
+
+/// How the stack falls into cleared lines.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum ClearGravity {
+    /// Rows above move down as a whole.
+    #[default]
+    Naive,
+    /// Cells of one piece that still touch fall together until they land.
+    /// Garbage rows count as a piece each, cells of boards set up front fall alone.
+    Sticky,
+    /// Every cell falls on its own.
+    Cascade,
+}
+
+impl FromStr for ClearGravity {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "naive" => Ok(ClearGravity::Naive),
+            "sticky" => Ok(ClearGravity::Sticky),
+            "cascade" => Ok(ClearGravity::Cascade),
+            name => Err(format!("unknown clear gravity `{name}`, expected naive, sticky or cascade")),
+        }
+    }
+}
+
+/// Clears the full lines, then the lines the falling cells fill, and so on.
+/// `lock_times` and `piece_ids` follow the cells around. Returns the lines cleared at each step.
+pub(super) fn clear(matrix: &mut Matrix, lock_times: &mut [Duration], piece_ids: &mut [u32], gravity: ClearGravity) -> Vec<usize> {
+    let mut steps = Vec::new();
+    loop {
+        let lines = matrix.full_lines();
+        if lines.is_empty() {
+            return steps;
+        }
+        steps.push(lines.len());
+
+        if gravity == ClearGravity::Naive {
+            remove_rows(&mut matrix.0, &lines, None);
+            remove_rows(lock_times, &lines, Duration::ZERO);
+            remove_rows(piece_ids, &lines, 0);
+            continue;
+        }
+        for y in lines {
+            matrix.0[y * Matrix::WIDTH..(y + 1) * Matrix::WIDTH].fill(None);
+        }
+        match gravity {
+            ClearGravity::Sticky => settle_chunks(matrix, lock_times, piece_ids),
+            _ => settle_cells(matrix, lock_times, piece_ids),
+        }
+    }
+}
+
+fn settle_cells(matrix: &mut Matrix, lock_times: &mut [Duration], piece_ids: &mut [u32]) {
+    for x in 0..Matrix::WIDTH {
+        let mut landing = 0;
+        for y in 0..Matrix::ROWS {
+            let from = y * Matrix::WIDTH + x;
+            if matrix.0[from].is_some() {
+                let to = landing * Matrix::WIDTH + x;
+                matrix.0.swap(from, to);
+                lock_times.swap(from, to);
+                piece_ids.swap(from, to);
+                landing += 1;
+            }
+        }
+    }
+}
+
+/// Drops chunks lowest first until none can fall.
+fn settle_chunks(matrix: &mut Matrix, lock_times: &mut [Duration], piece_ids: &mut [u32]) {
+    loop {
+        let mut moved = false;
+        for mut chunk in chunks(matrix, piece_ids) {
+            let can_fall = |matrix: &Matrix, chunk: &[usize]| chunk.iter().all(|&i| {
+                i >= Matrix::WIDTH && (matrix.0[i - Matrix::WIDTH].is_none() || chunk.contains(&(i - Matrix::WIDTH)))
+            });
+            while can_fall(matrix, &chunk) {
+                let cells = chunk.iter().map(|&i| (matrix.0[i].take(), lock_times[i], piece_ids[i])).collect::<Vec<_>>();
+                for (i, (cell, time, id)) in chunk.iter_mut().zip(cells) {
+                    *i -= Matrix::WIDTH;
+                    matrix.0[*i] = cell;
+                    lock_times[*i] = time;
+                    piece_ids[*i] = id;
+                }
+                moved = true;
+            }
+        }
+        if !moved {
+            return;
+        }
+    }
+}
+
+/// Groups of cells from the same piece touching by a side, lowest first.
+/// Cells of no piece, id 0, are a group each.
+fn chunks(matrix: &Matrix, piece_ids: &[u32]) -> Vec<Vec<usize>> {
+    let mut seen = [false; Matrix::SIZE];
+    let mut chunks = Vec::new();
+
+    for start in 0..Matrix::SIZE {
+        if seen[start] || matrix.0[start].is_none() {
+            continue;
+        }
+        seen[start] = true;
+        let mut chunk = vec![start];
+        let mut index = 0;
+        while let Some(&i) = chunk.get(index) {
+            let (x, y) = (i % Matrix::WIDTH, i / Matrix::WIDTH);
+            let neighbours = [
+                (x > 0).then(|| i - 1),
+                (x + 1 < Matrix::WIDTH).then_some(i + 1),
+                (y > 0).then(|| i - Matrix::WIDTH),
+                (y + 1 < Matrix::ROWS).then_some(i + Matrix::WIDTH),
+            ];
+            for next in neighbours.into_iter().flatten() {
+                let same_piece = piece_ids[i] != 0 && piece_ids[next] == piece_ids[i];
+                if !seen[next] && matrix.0[next].is_some() && same_piece {
+                    seen[next] = true;
+                    chunk.push(next);
+                }
+            }
+            index += 1;
+        }
+        chunks.push(chunk);
+    }
+    chunks
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn cleared(text: &str, gravity: ClearGravity) -> (Matrix, Vec<usize>) {
+        cleared_pieces(text, "", gravity)
+    }
+
+    /// `ids` draws the piece of every cell as a digit, like the board.
+    fn cleared_pieces(text: &str, ids: &str, gravity: ClearGravity) -> (Matrix, Vec<usize>) {
+        let mut matrix: Matrix = text.parse().unwrap();
+        let mut piece_ids = [0; Matrix::SIZE];
+        for (y, row) in ids.lines().map(str::trim).filter(|row| !row.is_empty()).rev().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                piece_ids[y * Matrix::WIDTH + x] = c.to_digit(10).unwrap_or(0);
+            }
+        }
+        let steps = clear(&mut matrix, &mut [Duration::ZERO; Matrix::SIZE], &mut piece_ids, gravity);
+        (matrix, steps)
+    }
+
+    #[test]
+    fn gravity_variants() {
+        let board = "
+            ZZ........
+            .ZZ......O
+            IIIIJJJ.LO
+            TTTTTTTTTT
+        ";
+        assert_eq!(cleared(board, ClearGravity::Naive), ("
+            ZZ........
+            .ZZ......O
+            IIIIJJJ.LO
+        ".parse().unwrap(), vec![1]));
+
+        assert_eq!(cleared(board, ClearGravity::Cascade), ("
+            .Z........
+            ZZZ......O
+            IIIIJJJ.LO
+        ".parse().unwrap(), vec![1]));
+
+        let chain = "
+            Z.........
+            ........O.
+            IIIIJJJS.S
+            TTTTTTTTTT
+        ";
+        assert_eq!(cleared(chain, ClearGravity::Naive).1, [1]);
+        assert_eq!(cleared(chain, ClearGravity::Sticky), ("Z.........".parse().unwrap(), vec![1, 1]));
+    }
+
+    #[test]
+    fn sticky_pieces_keep_apart() {
+        let board = "
+            ....OO....
+            ..OOOO....
+            ..OO......
+            TTTTTTTTTT
+            IIII......
+        ";
+        let ids = "
+            ....22....
+            ..1122....
+            ..11......
+            3333333333
+            4444......
+        ";
+        assert_eq!(cleared_pieces(board, ids, ClearGravity::Sticky), ("
+            ..OO......
+            ..OOOO....
+            IIIIOO....
+        ".parse().unwrap(), vec![1]));
+    }
+}
@@ -1,14 +1,24 @@
 use std::slice::ArrayChunks;
+use std::collections::VecDeque;
 use std::{ops::{Index, IndexMut}, time::Duration };
 use cgmath::{Vector2, Point2, EuclideanSpace};
-use rand::{prelude::{SliceRandom, ThreadRng}, thread_rng};
-use self::{piece::{Piece, Kind as PieceKind, Rotation},geometry::GridIncrement} ;
+use rand::{prelude::{SliceRandom, StdRng}, Rng, SeedableRng};
+use self::{piece::{Piece, Kind as PieceKind, Rotation, RotateKind},geometry::GridIncrement} ;
 
+pub use self::cascade::ClearGravity;
+pub use self::piece_set::PieceSet;
+
+mod cascade;
 pub mod piece;
+pub mod piece_set;
+pub mod notation;
 mod geometry;
 
-type Coordinate = Point2<usize>;
-type Offset = Vector2<isize>;
+pub type Coordinate = Point2<usize>;
+pub type Offset = Vector2<isize>;
+
+/// Time unit of delays and gravity in arcade rulesets.
+pub const FRAME: Duration = Duration::from_micros(16_667);
 
 
 #[derive(Copy,Clone, PartialEq, Debug)]
@@ -16,7 +26,7 @@ pub enum MoveKind { Left, Right }
 
 
 impl MoveKind {
-    fn offset(&self) -> Offset {
+    pub fn offset(&self) -> Offset {
         match self {
             MoveKind::Left => Offset::new(-1,0),
             MoveKind::Right => Offset::new(1, 0),
@@ -25,23 +35,113 @@ impl MoveKind {
 }
 
 
+/// Cloning saves the whole game, randomizer included, for rollback to restore.
+#[derive(Clone)]
 pub struct Engine {
     matrix: Matrix,
+    piece_set: PieceSet,
+    /// Set by `set_sequence`, the queue never refills.
+    fixed_sequence: bool,
     bag: Vec<PieceKind>,
-    rng: ThreadRng,
+    queue: VecDeque<PieceKind>,
+    hold: Option<PieceKind>,
+    hold_used: bool,
+    rng: StdRng,
     cursor: Option<Piece>,
     level: u8,
+    /// Lines cleared so far.
+    lines: usize,
+    /// Start level and lines per level up, when clears drive the level.
+    progression: Option<(u8, usize)>,
+    topped_out: bool,
+    /// Kick used by the last rotation, until the cursor moves otherwise.
+    last_kick: Option<Offset>,
+    /// Set when a piece locks, consumed by `line_clear`.
+    locked: bool,
+    spin: Option<Spin>,
+    /// Consecutive clearing pieces so far, minus one.
+    combo: Option<usize>,
+    back_to_back: bool,
+    /// Game time so far, advanced by whoever runs the game.
+    clock: Duration,
+    /// The game ends when the clock gets there.
+    time_limit: Option<Duration>,
+    timing: Timing,
+    /// Clock time the next piece may spawn at.
+    spawn_at: Duration,
+    /// The stack waiting out the line clear delay.
+    clearing: Option<Clearing>,
+    clear_gravity: ClearGravity,
+    /// Pieces spawn big, see `Piece::big`.
+    big: bool,
+    /// Clock time the cursor came to rest, for the lock delay.
+    resting_since: Option<Duration>,
+    /// Rotation and hold pressed between pieces, applied on spawn (IRS and IHS).
+    initial_rotation: Option<RotateKind>,
+    initial_hold: bool,
+    visibility: Visibility,
+    /// Clock time every cell of the matrix locked at, row major like the matrix.
+    lock_times: [Duration; Matrix::SIZE],
+    /// Which piece every cell came from, for sticky clear gravity, 0 for none.
+    piece_ids: [u32; Matrix::SIZE],
+    /// Id of the latest piece or garbage row.
+    last_piece_id: u32,
+    /// The whole stack shows until then after clearing lines.
+    revealed_until: Duration,
 }
 
 impl Engine {
+    pub const QUEUE_LEN: usize = 5;
+    pub const MAX_GRAVITY_LEVEL: u8 = 20;
+    /// How long clearing lines shows an invisible or faded stack for.
+    pub const REVEAL_TIME: Duration = Duration::from_millis(500);
+
     pub fn new() -> Self {
-        Engine {
+        Self::with_rng(StdRng::from_entropy())
+    }
+
+    pub fn with_seed(seed: u64) -> Self {
+        Self::with_rng(StdRng::seed_from_u64(seed))
+    }
+
+    fn with_rng(rng: StdRng) -> Self {
+        let mut engine = Engine {
             matrix: Matrix::blank(),
+            piece_set: PieceSet::default(),
+            fixed_sequence: false,
             bag: Vec::new(),
-            rng: thread_rng(),
+            queue: VecDeque::with_capacity(Self::QUEUE_LEN),
+            hold: None,
+            hold_used: false,
+            rng,
             cursor: None,
             level: 1,
-        }
+            lines: 0,
+            progression: None,
+            topped_out: false,
+            last_kick: None,
+            locked: false,
+            spin: None,
+            combo: None,
+            back_to_back: false,
+            clock: Duration::ZERO,
+            time_limit: None,
+            timing: Timing::default(),
+            spawn_at: Duration::ZERO,
+            clearing: None,
+            clear_gravity: ClearGravity::Naive,
+            big: false,
+            resting_since: None,
+            initial_rotation: None,
+            initial_hold: false,
+            visibility: Visibility::Visible,
+            lock_times: [Duration::ZERO; Matrix::SIZE],
+            piece_ids: [0; Matrix::SIZE],
+            last_piece_id: 0,
+            revealed_until: Duration::ZERO,
+        };
+        engine.fill_queue();
+        engine
     }
 
     pub fn with_matrix(matrix:Matrix) ->Self {
@@ -51,24 +151,309 @@ impl Engine {
         }
     }
 
+    /// Replaces the stack, for setups and puzzles.
+    pub fn set_matrix(&mut self, matrix: Matrix) {
+        self.matrix = matrix;
+        self.lock_times = [self.clock; Matrix::SIZE];
+        self.piece_ids = [0; Matrix::SIZE];
+        self.clearing = None;
+    }
+
     fn refill_bag(&mut self) {
         debug_assert!(self.bag.is_empty());
-        self.bag.extend_from_slice(PieceKind::ALL.as_slice());
+        self.bag.extend_from_slice(self.piece_set.kinds());
         self.bag.shuffle(&mut self.rng)
     }
 
+    fn fill_queue(&mut self) {
+        while !self.fixed_sequence && self.queue.len() < Self::QUEUE_LEN {
+            if self.bag.is_empty() {
+                self.refill_bag();
+            }
+            let kind = self.bag.pop().unwrap();
+            self.queue.push_back(kind);
+        }
+    }
+
+    fn next_kind(&mut self) -> Option<PieceKind> {
+        let kind = self.queue.pop_front()?;
+        self.fill_queue();
+        Some(kind)
+    }
+
+    pub fn piece_set(&self) -> &PieceSet {
+        &self.piece_set
+    }
+
+    /// Deals from `piece_set` from now on, starting over with the queue.
+    pub fn set_piece_set(&mut self, piece_set: PieceSet) {
+        self.piece_set = piece_set;
+        self.fixed_sequence = false;
+        self.bag.clear();
+        self.queue.clear();
+        self.fill_queue();
+    }
+
+    /// Deals `kinds` in order and nothing after, for puzzles.
+    /// The held piece comes out last once the queue runs dry.
+    pub fn set_sequence(&mut self, kinds: &[PieceKind]) {
+        self.fixed_sequence = true;
+        self.bag.clear();
+        self.queue = kinds.iter().copied().collect();
+    }
+
+    /// Upcoming pieces, nearest first.
+    pub fn queue(&self) -> impl Iterator<Item = PieceKind> + '_ {
+        self.queue.iter().copied()
+    }
+
+    /// What is left of the current bag, dealt once the queue moves on, nearest first.
+    pub fn rest_of_bag(&self) -> impl Iterator<Item = PieceKind> + '_ {
+        self.bag.iter().rev().copied()
+    }
+
+    /// Starts over on an empty matrix with a fresh queue, for drills.
+    /// Settings, the clock and the randomizer carry on.
+    pub fn restart(&mut self) {
+        self.set_matrix(Matrix::blank());
+        self.cursor = None;
+        self.hold = None;
+        self.hold_used = false;
+        self.topped_out = false;
+        self.locked = false;
+        self.spin = None;
+        self.combo = None;
+        self.back_to_back = false;
+        self.set_piece_set(self.piece_set.clone());
+    }
+
+    pub fn held(&self) -> Option<PieceKind> {
+        self.hold
+    }
+
+    pub fn can_hold(&self) -> bool {
+        !self.hold_used
+    }
+
+    pub fn is_topped_out(&self) -> bool {
+        self.topped_out
+    }
+
+    pub fn level(&self) -> u8 {
+        self.level
+    }
+
+    /// Sets the gravity level, at least 1.
+    pub fn set_level(&mut self, level: u8) {
+        self.level = level.max(1);
+    }
+
+    /// Starts at level `start` and levels up every `lines_per_level` lines
+    /// cleared from now on.
+    pub fn set_level_progression(&mut self, start: u8, lines_per_level: usize) {
+        self.set_level(start);
+        self.progression = Some((self.level, lines_per_level.max(1)));
+        self.lines = 0;
+    }
+
+    pub fn lines(&self) -> usize {
+        self.lines
+    }
+
+    pub fn clock(&self) -> Duration {
+        self.clock
+    }
+
+    /// Moves the game clock to `time`, stopping at the time limit.
+    /// Lines that waited out the line clear delay go away.
+    pub fn advance_clock(&mut self, time: Duration) {
+        self.clock = self.time_limit.map_or(time, |limit| time.min(limit));
+        if self.clearing.as_ref().is_some_and(|clearing| self.clock >= clearing.at) {
+            let clearing = self.clearing.take().unwrap();
+            self.matrix = clearing.matrix;
+            self.lock_times = clearing.lock_times;
+            self.piece_ids = clearing.piece_ids;
+        }
+    }
+
+    pub fn set_time_limit(&mut self, limit: Option<Duration>) {
+        self.time_limit = limit;
+    }
+
+    pub fn time_left(&self) -> Option<Duration> {
+        self.time_limit.map(|limit| limit.saturating_sub(self.clock))
+    }
+
+    pub fn is_time_up(&self) -> bool {
+        self.time_left().is_some_and(|left| left.is_zero())
+    }
+
+    pub fn timing(&self) -> Timing {
+        self.timing
+    }
+
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.timing = timing;
+    }
+
+    pub fn phase(&self) -> Phase {
+        match (self.cursor, &self.clearing) {
+            (Some(_), _) => Phase::Falling,
+            (None, Some(_)) => Phase::Clearing,
+            (None, None) => Phase::Entry,
+        }
+    }
+
+    /// Whether the delays after the last lock are over, so the next piece can spawn.
+    pub fn is_spawn_ready(&self) -> bool {
+        self.clearing.is_none() && self.clock >= self.spawn_at
+    }
+
+    pub fn is_big(&self) -> bool {
+        self.big
+    }
+
+    /// Big mode, pieces from the next spawn on are made of 2x2 squares.
+    pub fn set_big(&mut self, big: bool) {
+        self.big = big;
+    }
+
+    pub fn clear_gravity(&self) -> ClearGravity {
+        self.clear_gravity
+    }
+
+    pub fn set_clear_gravity(&mut self, gravity: ClearGravity) {
+        self.clear_gravity = gravity;
+    }
+
+    pub fn visibility(&self) -> Visibility {
+        self.visibility
+    }
+
+    /// How locked cells show, they collide all the same.
+    pub fn set_visibility(&mut self, visibility: Visibility) {
+        self.visibility = visibility;
+    }
+
+    /// How visible a cell that locked at `locked_at` is now, from 0 to 1.
+    pub fn cell_alpha(&self, locked_at: Duration) -> f32 {
+        if self.clock < self.revealed_until {
+            return 1.0;
+        }
+        match self.visibility {
+            Visibility::Visible => 1.0,
+            Visibility::Invisible => 0.0,
+            Visibility::Fading(time) if time.is_zero() => 0.0,
+            Visibility::Fading(time) => {
+                let age = self.clock.saturating_sub(locked_at);
+                1.0 - (age.as_secs_f32() / time.as_secs_f32()).min(1.0)
+            },
+        }
+    }
+
+    /// Empties the matrix, for credit rolls.
+    pub fn clear_stack(&mut self) {
+        self.matrix = Matrix::blank();
+        self.lock_times = [Duration::ZERO; Matrix::SIZE];
+        self.piece_ids = [0; Matrix::SIZE];
+        self.clearing = None;
+    }
+
+    pub fn matrix(&self) -> &Matrix {
+        &self.matrix
+    }
+
+    pub fn cursor(&self) -> Option<Piece> {
+        self.cursor
+    }
+
+    /// Puts the next piece from the queue in play.
+    /// Fails (and tops out) when the spawn position is blocked, or without
+    /// topping out when a fixed sequence ran out of pieces.
+    /// A hold buffered during the delays swaps the piece first, a buffered
+    /// rotation turns it if it fits without kicks.
+    pub fn spawn(&mut self) -> Result<(), ()> {
+        let Some(kind) = self.next_kind().or_else(|| self.hold.take()) else {
+            return Err(());
+        };
+        let swappable = self.hold.is_some() || !self.queue.is_empty();
+        if std::mem::take(&mut self.initial_hold) && !self.hold_used && swappable {
+            self.hold_used = true;
+            let kind = match self.hold.replace(kind) {
+                Some(held) => held,
+                None => self.next_kind().unwrap(),
+            };
+            return self.spawn_kind(kind);
+        }
+        self.spawn_kind(kind)
+    }
+
+    fn spawn_kind(&mut self, kind: PieceKind) -> Result<(), ()> {
+        let mut piece = match self.big {
+            true => Piece::spawned_big(kind),
+            false => Piece::spawned(kind),
+        };
+        let rotated = self.initial_rotation.take().map(|rotate_kind| piece.rotated(rotate_kind));
+        if let Some(rotated) = rotated.filter(|rotated| !self.matrix.is_clipping(rotated)) {
+            piece = rotated;
+        }
+        if self.matrix.is_clipping(&piece) {
+            self.topped_out = true;
+            return Err(());
+        }
+        self.cursor = Some(piece);
+        self.last_kick = None;
+        self.resting_since = None;
+        self.settle();
+        Ok(())
+    }
+
+    /// Between pieces, holds the next one as it spawns when the timing allows it.
+    pub fn hold(&mut self) -> Result<(), ()> {
+        if self.hold_used || (self.hold.is_none() && self.queue.is_empty()) {
+            return Err(());
+        }
+        let Some(cursor) = self.cursor.take() else {
+            if !self.timing.initial_actions || self.topped_out {
+                return Err(());
+            }
+            self.initial_hold = true;
+            return Ok(());
+        };
+        self.hold_used = true;
+        match self.hold.replace(cursor.kind) {
+            Some(kind) => self.spawn_kind(kind),
+            None => self.spawn(),
+        }
+    }
+
     fn place_cursor(&mut self,) {
         let cursor = self.cursor.take().expect("Called place cursor without cursor");
-        
-        assert!(
-            self.matrix.placeable(&cursor), 
-            "Trying to place cursor in unplaceble loc {:?}", cursor
-        );
-        
-        let color = cursor.kind.color();
-        for coordinate in cursor.cells().unwrap() {
-            self.matrix[coordinate]  = Some(color);
+        self.hold_used = false;
+
+        if !self.matrix.placeable(&cursor) {
+            self.topped_out = true;
+            return;
+        }
+        self.spin = self.last_kick.and_then(|kick| self.matrix.t_spin(&cursor, kick));
+        self.locked = true;
+        self.matrix.place(&cursor);
+        self.last_piece_id += 1;
+        for coord in cursor.squares().unwrap() {
+            self.lock_times[Matrix::indexing(coord)] = self.clock;
+            self.piece_ids[Matrix::indexing(coord)] = self.last_piece_id;
+        }
+        self.spawn_at = self.clock + self.timing.are;
+    }
 
+    /// Drops the cursor to the floor under 20G, where it never hangs in the air.
+    fn settle(&mut self) {
+        if self.timing.gravity.is_some_and(|gravity| gravity >= Timing::MAX_GRAVITY) {
+            while let Some(new) = self.ticked_down_cursor() {
+                self.cursor = Some(new);
+                self.last_kick = None;
+                self.resting_since = None;
+            }
         }
     }
 
@@ -77,28 +462,81 @@ impl Engine {
             return Ok(());
         };
 
-        let new = cursor.moved_by(move_kind.offset());
+        let new = cursor.stepped(move_kind.offset());
 
         if self.matrix.is_clipping(&new) {
             return Err(());
             
         }
         self.cursor = Some(new);
+        self.last_kick = None;
+        self.settle();
         Ok(())
     }
 
-    pub fn cursor_info(&self) -> Option<([Coordinate; Piece::CELL_COUNT], Color)> {
+    /// Between pieces, turns the next one as it spawns when the timing allows it.
+    pub fn rotate_cursor(&mut self, rotate_kind: RotateKind) -> Result<(), ()> {
+        let Some(cursor) = self.cursor.as_ref() else {
+            if self.timing.initial_actions {
+                self.initial_rotation = Some(rotate_kind);
+            }
+            return Ok(());
+        };
+
+        let new = self.matrix.rotated(cursor, rotate_kind).ok_or(())?;
+        self.last_kick = Some(new.position - cursor.rotated(rotate_kind).position);
+        self.cursor = Some(new);
+        self.settle();
+        Ok(())
+    }
+
+    pub fn cursor_info(&self) -> Option<(Vec<Coordinate>, Color)> {
         let cursor = self.cursor?;
         Some((cursor.cells().unwrap(), cursor.kind.color()))
     }
 
     pub fn db_test_cursor(&mut self, kind: PieceKind, position: Offset) {
-        let piece = Piece {kind, rotation: Rotation::N, position};
+        let piece = Piece {kind, rotation: Rotation::N, position, big: false};
         self.cursor = Some(piece);
     }
 
     fn step_down(&mut self ) {
         self.cursor = Some(self.ticked_down_cursor().unwrap());
+        self.last_kick = None;
+        self.resting_since = None;
+    }
+
+    /// Gravity's turn: the cursor falls, or locks once it rested for the
+    /// lock delay. Returns whether it locked.
+    pub fn fall(&mut self) -> bool {
+        if self.cursor.is_none() {
+            return false;
+        }
+        if !self.cursor_hit_down() {
+            let rows = self.timing.gravity.map_or(1, |gravity| (gravity as usize).max(1));
+            for _ in 0..rows {
+                if !self.soft_drop() {
+                    break;
+                }
+            }
+            return false;
+        }
+
+        let resting_since = *self.resting_since.get_or_insert(self.clock);
+        if self.clock - resting_since < self.timing.lock_delay {
+            return false;
+        }
+        self.hard_drop();
+        true
+    }
+
+    /// Moves the cursor one row down, returns false when it is resting.
+    pub fn soft_drop(&mut self) -> bool {
+        if self.cursor_hit_down() || self.cursor.is_none() {
+            return false;
+        }
+        self.step_down();
+        true
     }
 
     pub fn cursor_hit_down(&self) -> bool {
@@ -111,6 +549,7 @@ impl Engine {
         let Some(cursor) = self.cursor else {
             return None; 
         };
+        // A cell at a time even for big pieces, so they rest on stacks of any height.
         let new = cursor.moved_by(Offset::new(0,-1));
         (!self.matrix.is_clipping(&new)).then_some(new)
     }
@@ -118,48 +557,242 @@ impl Engine {
     pub fn hard_drop(&mut self) {
         while let Some(new) = self.ticked_down_cursor() {
             self.cursor = Some(new);
+            self.last_kick = None;
         }
         self.place_cursor();        
     }
 
-    pub fn cells(&self) ->CellIter <'_> {
-        CellIter {
-            position: Coordinate::origin(),
-            cell_iter: self.matrix.0.iter(),
-        }
+    /// The visible cells with the clock time each one locked at.
+    pub fn cells(&self) -> CellIter<'_> {
+        CellIter { lock_times: Some(self.lock_times.iter()), ..self.matrix.cells() }
     }
 
+    /// Time between gravity steps, stops getting shorter past `MAX_GRAVITY_LEVEL`
+    /// or a frame when the timing sets the gravity.
     pub fn drop_time(&self) -> Duration {
-        let level_index = self.level - 1;
+        if let Some(gravity) = self.timing.gravity {
+            return FRAME.div_f32(gravity.min(1.0));
+        }
+        let level_index = self.level.min(Self::MAX_GRAVITY_LEVEL) - 1;
         let sec_per_line  = (0.8 - (level_index as f32 * 0.007 )).powi(level_index as _);
         Duration::from_secs_f32(sec_per_line)
     }
 
-    pub fn line_clear(&mut self, mut animation: impl FnMut(&[usize])) {
+    /// Pushes `rows` garbage rows in from the bottom. The first row has its hole at
+    /// `hole`, each later row moves the hole to a random column with chance `messiness`.
+    /// The stack rises into the buffer above the matrix, a cursor in the way is
+    /// pushed up over it. The game ends once garbage would push a cell out
+    /// of the buffer, or when the next piece can't spawn.
+    pub fn add_garbage(&mut self, rows: usize, hole: usize, messiness: f64) -> Result<(), ()> {
+        let mut hole = hole.min(Matrix::WIDTH - 1);
+        for row in 0..rows {
+            if row > 0 && self.rng.gen_bool(messiness.clamp(0.0, 1.0)) {
+                hole = (hole + self.rng.gen_range(1..Matrix::WIDTH)) % Matrix::WIDTH;
+            }
+            if self.matrix.raise(hole).is_err() {
+                self.topped_out = true;
+                return Err(());
+            }
+            self.last_piece_id += 1;
+            raise_cells(&mut self.lock_times, self.clock);
+            raise_cells(&mut self.piece_ids, self.last_piece_id);
+            if let Some(clearing) = &mut self.clearing {
+                let _ = clearing.matrix.raise(hole);
+                raise_cells(&mut clearing.lock_times, self.clock);
+                raise_cells(&mut clearing.piece_ids, self.last_piece_id);
+            }
+        }
+
+        if let Some(mut cursor) = self.cursor {
+            while self.matrix.is_clipping(&cursor) {
+                cursor = cursor.moved_by(Offset::new(0, 1));
+            }
+            self.cursor = Some(cursor);
+        }
+        Ok(())
+    }
+
+    /// A column picked by the engine's randomizer, for garbage holes.
+    pub fn random_column(&mut self) -> usize {
+        self.rng.gen_range(0..Matrix::WIDTH)
+    }
+
+    /// Clears full lines and scores the piece that locked them.
+    /// Returns `None` when no piece locked since the last call.
+    /// With a line clear delay the lines stay until the clock gets past it.
+    pub fn line_clear(&mut self, mut animation: impl FnMut(&[usize])) -> Option<LineClear> {
+        if self.clearing.is_some() {
+            return None;
+        }
         let lines= self.matrix.full_lines();
         animation(lines.as_slice());
-        self.matrix.clear_line(lines.as_slice())
+        let mut cleared = Clearing {
+            matrix: self.matrix,
+            lock_times: self.lock_times,
+            piece_ids: self.piece_ids,
+            at: self.clock + self.timing.line_clear,
+        };
+        let steps = cascade::clear(&mut cleared.matrix, &mut cleared.lock_times, &mut cleared.piece_ids, self.clear_gravity);
+        let total = steps.iter().sum::<usize>();
+        let perfect_clear = cleared.matrix.0.iter().all(Option::is_none);
+        if lines.is_empty() || self.timing.line_clear.is_zero() {
+            self.matrix = cleared.matrix;
+            self.lock_times = cleared.lock_times;
+            self.piece_ids = cleared.piece_ids;
+        } else {
+            self.clearing = Some(cleared);
+        }
+        if !lines.is_empty() {
+            self.spawn_at = self.clock + self.timing.line_clear + self.timing.line_are;
+            self.revealed_until = self.clock + self.timing.line_clear + Self::REVEAL_TIME;
+        }
+        self.lines += total;
+        if let Some((start, lines_per_level)) = self.progression {
+            let gained = (self.lines / lines_per_level).min(u8::MAX as usize) as u8;
+            self.level = start.saturating_add(gained);
+        }
+
+        if !std::mem::take(&mut self.locked) {
+            return None;
+        }
+        let spin = self.spin.take();
+        if lines.is_empty() {
+            self.combo = None;
+            return Some(LineClear { spin, ..LineClear::default() });
+        }
+
+        let combo = self.combo.map_or(0, |combo| combo + 1);
+        self.combo = Some(combo);
+        let difficult = lines.len() >= 4 || spin.is_some();
+        let back_to_back = difficult && self.back_to_back;
+        self.back_to_back = difficult;
+
+        Some(LineClear {
+            lines: total,
+            spin,
+            combo,
+            back_to_back,
+            perfect_clear,
+            chain: steps.len() - 1,
+        })
     }
 }
 
+/// The stack as it will be once the line clear delay is over.
+#[derive(Clone)]
+struct Clearing {
+    matrix: Matrix,
+    lock_times: [Duration; Matrix::SIZE],
+    piece_ids: [u32; Matrix::SIZE],
+    at: Duration,
+}
+
+/// Shifts per cell data up a row like `Matrix::raise`, `fill` goes in the bottom row.
+fn raise_cells<T: Copy>(cells: &mut [T; Matrix::SIZE], fill: T) {
+    cells.copy_within(..Matrix::SIZE - Matrix::WIDTH, Matrix::WIDTH);
+    cells[..Matrix::WIDTH].fill(fill);
+}
+
+
+/// Delays and gravity of a ruleset, all zero by default for instant
+/// spawns and clears and gravity following the level.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct Timing {
+    /// Entry delay between a lock without lines and the next spawn.
+    pub are: Duration,
+    /// Entry delay after the line clear delay, when the lock cleared lines.
+    pub line_are: Duration,
+    /// Time full lines stay before the stack falls.
+    pub line_clear: Duration,
+    /// Time a resting cursor waits for before gravity locks it.
+    pub lock_delay: Duration,
+    /// Rows per frame, `MAX_GRAVITY` and above drops pieces to the floor at once.
+    pub gravity: Option<f32>,
+    /// Rotations and holds between pieces apply to the next one (IRS and IHS).
+    pub initial_actions: bool,
+}
+
+impl Timing {
+    pub const MAX_GRAVITY: f32 = 20.0;
+
+    /// NES style: entry and line clear delays, no lock delay or initial actions.
+    pub const CLASSIC: Timing = Timing {
+        are: Duration::from_micros(16_667 * 10),
+        line_are: Duration::from_micros(16_667 * 10),
+        line_clear: Duration::from_micros(16_667 * 18),
+        lock_delay: Duration::ZERO,
+        gravity: None,
+        initial_actions: false,
+    };
+}
+
+/// How locked cells show on screen.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum Visibility {
+    #[default]
+    Visible,
+    Invisible,
+    /// Gone after being locked for the given time.
+    Fading(Duration),
+}
+
+/// Where the engine is between pieces.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Phase {
+    /// A piece is in play.
+    Falling,
+    /// Full lines wait out the line clear delay.
+    Clearing,
+    /// Waiting out the entry delay, or for `spawn`.
+    Entry,
+}
+
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Spin { Mini, Full }
+
+/// What locking a piece achieved, as reported by `Engine::line_clear`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct LineClear {
+    pub lines: usize,
+    pub spin: Option<Spin>,
+    /// Clearing pieces in a row before this one, only meaningful when `lines > 0`.
+    pub combo: usize,
+    /// A tetris or spin clear following another one.
+    pub back_to_back: bool,
+    pub perfect_clear: bool,
+    /// Further clears set off by cells falling into the cleared lines,
+    /// only under sticky or cascade gravity. `lines` counts them all.
+    pub chain: usize,
+}
 
-#[derive(Copy,Clone, PartialEq, Debug)]
-pub enum Color {Yellow, Cyan, Purple, Orange, Blue, Green, Red}
 
+#[derive(Copy,Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Color {Yellow, Cyan, Purple, Orange, Blue, Green, Red, Garbage, Rgb(u8, u8, u8)}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Matrix([Option<Color>; Self::SIZE]);
 
 
 impl Matrix {
     pub const WIDTH: usize = 10;
     pub const HEIGHT: usize = 20;
-    const SIZE: usize = Self::HEIGHT * Self::WIDTH;
+    /// Hidden rows above the visible ones, where garbage can lift the stack.
+    pub const BUFFER: usize = 20;
+    const ROWS: usize = Self::HEIGHT + Self::BUFFER;
+    const SIZE: usize = Self::ROWS * Self::WIDTH;
 
-    fn on_matrix(coord: Coordinate) -> bool {
+    pub fn on_matrix(coord: Coordinate) -> bool {
         //x < Self::WIDTH && y < Self::HEIGHT
         Self::valid_coord(coord) && coord.y < Self::HEIGHT
     }
 
-    fn valid_coord(coord: Coordinate) -> bool {
+    /// Whether `coord` is on the matrix or in the buffer above it.
+    fn in_buffer(coord: Coordinate) -> bool {
+        Self::valid_coord(coord) && coord.y < Self::ROWS
+    }
+
+    pub fn valid_coord(coord: Coordinate) -> bool {
         coord.x < Self::WIDTH 
     }
 
@@ -171,21 +804,76 @@ impl Matrix {
         Self([None; Self::SIZE])
     }
 
-    fn is_clipping(&self, piece: &Piece) -> bool {
-        let Some(cells) = piece.cells() else {
+    /// The visible cells, the buffer left out. A matrix keeps no lock
+    /// times, its cells all count as locked at the start.
+    pub fn cells(&self) -> CellIter<'_> {
+        CellIter {
+            position: Coordinate::origin(),
+            cell_iter: self.0[..Self::HEIGHT * Self::WIDTH].iter(),
+            lock_times: None,
+        }
+    }
+
+    pub fn is_clipping(&self, piece: &Piece) -> bool {
+        let Some(mut cells) = piece.squares() else {
             return true;
         };
-        cells.into_iter().any(|coord|
+        cells.any(|coord|
             !Matrix::valid_coord(coord) ||
-            (!Matrix::on_matrix(coord) && self[coord].is_some())
+            (Matrix::in_buffer(coord) && self[coord].is_some())
         )
     }
 
-    fn placeable(&self, piece: &Piece) -> bool {
-        let Some(cells) = piece.cells() else {
+    /// Rotates `piece` trying each wall kick in turn.
+    pub fn rotated(&self, piece: &Piece, rotate_kind: RotateKind) -> Option<Piece> {
+        let turned = piece.rotated(rotate_kind);
+        piece.kind.kicks(piece.rotation, turned.rotation)
+            .into_iter()
+            .map(|kick| turned.stepped(kick))
+            .find(|candidate| !self.is_clipping(candidate))
+    }
+
+    pub fn place(&mut self, piece: &Piece) {
+        let color = piece.kind.color();
+        for coordinate in piece.squares().unwrap() {
+            self[coordinate] = Some(color);
+        }
+    }
+
+    /// Three corner T-spin check for a T resting at `piece`, reached by a rotation
+    /// using `kick`. Only one front corner filled makes it a mini, unless
+    /// the rotation took the long kick.
+    pub fn t_spin(&self, piece: &Piece, kick: Offset) -> Option<Spin> {
+        if piece.kind != PieceKind::T || piece.big {
+            return None;
+        }
+        let center = piece.srs_center()?;
+        let front = Offset::new(0, 1) * piece.rotation;
+        let side = Offset::new(front.y, -front.x);
+        let filled = |corner: Offset| {
+            let Some(coord) = corner.cast::<usize>().map(Coordinate::from_vec) else {
+                return true;
+            };
+            !Matrix::valid_coord(coord) || (Matrix::in_buffer(coord) && self[coord].is_some())
+        };
+
+        let front_corners = [side, -side].map(|side| filled(center + front + side));
+        let back_corners = [side, -side].map(|side| filled(center - front + side));
+        let count = front_corners.into_iter().chain(back_corners).filter(|&filled| filled).count();
+
+        match (count, front_corners) {
+            (0..=2, _) => None,
+            (_, [true, true]) => Some(Spin::Full),
+            _ if kick.x.abs() == 1 && kick.y.abs() == 2 => Some(Spin::Full),
+            _ => Some(Spin::Mini),
+        }
+    }
+
+    pub fn placeable(&self, piece: &Piece) -> bool {
+        let Some(mut cells) = piece.squares() else {
             return false;
         };
-        cells.into_iter().all(|coord|
+        cells.all(|coord|
             Matrix::on_matrix(coord) && self[coord].is_none()
         )
     }
@@ -195,7 +883,7 @@ impl Matrix {
     }
 
 
-    fn full_lines(&self) -> Vec<usize> {
+    pub fn full_lines(&self) -> Vec<usize> {
         self.lines()
             .enumerate()
             .filter(|(_,line)| line.iter().all(Option::is_some))
@@ -203,13 +891,37 @@ impl Matrix {
             .collect()
     }
 
-    fn clear_line(&mut self, indexes: &[usize]) {
-        
-        for index in indexes.iter().rev(){
-            let start_line = Self::WIDTH * (index + 1);
-            self.0.copy_within(start_line.., Self::WIDTH * index);
-            self.0[Self::SIZE - Self::WIDTH..].fill(None);
+    /// Rows holding garbage cells.
+    pub fn garbage_rows(&self) -> usize {
+        self.lines().filter(|line| line.contains(&Some(Color::Garbage))).count()
+    }
+
+    /// Shifts everything up a row and fills the bottom with garbage around `hole`.
+    /// Rows leaving the visible matrix go into the buffer, fails without
+    /// changes if the top buffer row is occupied.
+    pub fn raise(&mut self, hole: usize) -> Result<(), ()> {
+        if self.0[Self::SIZE - Self::WIDTH..].iter().any(Option::is_some) {
+            return Err(());
         }
+        self.0.copy_within(..Self::SIZE - Self::WIDTH, Self::WIDTH);
+        self.0[..Self::WIDTH].fill(Some(Color::Garbage));
+        self.0[hole] = None;
+        Ok(())
+    }
+
+    pub fn clear_line(&mut self, indexes: &[usize]) {
+        remove_rows(&mut self.0, indexes, None);
+    }
+}
+
+/// Takes rows out of a row major grid as wide as the matrix, the rows
+/// above fall and `empty` fills the top.
+fn remove_rows<T: Copy>(cells: &mut [T], indexes: &[usize], empty: T) {
+    let len = cells.len();
+    for index in indexes.iter().rev(){
+        let start_line = Matrix::WIDTH * (index + 1);
+        cells.copy_within(start_line.., Matrix::WIDTH * index);
+        cells[len - Matrix::WIDTH..].fill(empty);
     }
 }
 
@@ -218,7 +930,7 @@ impl Index<Coordinate> for Matrix {
     type Output = Option<Color>;
 
     fn index(&self, coord: Coordinate) -> &Self::Output {
-        assert!(Self::on_matrix(coord));
+        assert!(Self::in_buffer(coord));
         &self.0[Self::indexing(coord)]
     }
 }
@@ -227,7 +939,7 @@ impl Index<Coordinate> for Matrix {
 impl IndexMut<Coordinate> for Matrix {
     
     fn index_mut(&mut self, coord: Coordinate) -> &mut Self::Output {
-        assert!(Self::on_matrix(coord));
+        assert!(Self::in_buffer(coord));
         &mut self.0[Self::indexing(coord)]
     }
 }
@@ -236,10 +948,12 @@ impl IndexMut<Coordinate> for Matrix {
 pub struct CellIter<'matrix> {
     position: Coordinate,
     cell_iter: ::std::slice::Iter<'matrix, Option<Color>>, //here <'matrix> is lifetime parameter
+    /// Clock time each cell locked at, when iterating an engine's matrix.
+    lock_times: Option<::std::slice::Iter<'matrix, Duration>>,
 }
 
 impl<'matrix> Iterator for CellIter<'matrix>{
-    type Item = (Coordinate, Option<Color>) ;
+    type Item = (Coordinate, Option<Color>, Duration) ;
 
     fn next(&mut self) -> Option<Self::Item> {
         /*if let Some(cell) = self.cells.next() {
@@ -261,8 +975,10 @@ impl<'matrix> Iterator for CellIter<'matrix>{
             self.position.y += 1;
         }*/ // moved to geometry
 
+        let locked_at = self.lock_times.as_mut().and_then(Iterator::next).copied().unwrap_or_default();
+
         self.position.grid_inc();
-        Some((coord, cell))
+        Some((coord, cell, locked_at))
     } 
 }
 
@@ -281,27 +997,221 @@ mod test{
         let mut iter = CellIter {
             position: Coordinate::origin(),
             cell_iter: matrix.0.iter(),
+            lock_times: None,
         };
 
         let first_five = (&mut iter).take(5).collect::<Vec<_>>();
         
         assert_eq!(first_five, vec![
-            (Coordinate::new(0,0), None),
-            (Coordinate::new(1,0), None),
-            (Coordinate::new(2,0), Some(Color::Blue)),
-            (Coordinate::new(3,0), None),
-            (Coordinate::new(4,0), None),
+            (Coordinate::new(0,0), None, Duration::ZERO),
+            (Coordinate::new(1,0), None, Duration::ZERO),
+            (Coordinate::new(2,0), Some(Color::Blue), Duration::ZERO),
+            (Coordinate::new(3,0), None, Duration::ZERO),
+            (Coordinate::new(4,0), None, Duration::ZERO),
         ]);
 
         let other_item = (&mut iter).skip(8).next();
         assert_eq!(
             other_item, 
-            Some((Coordinate::new(3,1), Some(Color::Green)))
+            Some((Coordinate::new(3,1), Some(Color::Green), Duration::ZERO))
         );
 
 
-        assert!(iter.all(|(_, contents)| contents.is_none()));
+        assert!(iter.all(|(_, contents, _)| contents.is_none()));
+
+
+    }
+
+    #[test]
+    fn clean_garbage() {
+        let mut engine = Engine::with_matrix("..TTT.....".parse().unwrap());
+        engine.add_garbage(2, 7, 0.0).unwrap();
+
+        assert_eq!(engine.matrix, "
+            ..TTT.....
+            GGGGGGG.GG
+            GGGGGGG.GG
+        ".parse().unwrap());
+    }
+
+    #[test]
+    fn garbage_tops_out() {
+        let mut engine = Engine::with_seed(1);
+        engine.spawn().unwrap();
+        engine.add_garbage(Matrix::HEIGHT - 1, 0, 1.0).unwrap();
+
+        let cursor = engine.cursor().unwrap();
+        assert!(!engine.matrix.is_clipping(&cursor));
+        assert!(!engine.is_topped_out());
+
+        engine.add_garbage(Matrix::BUFFER, 0, 1.0).unwrap();
+        assert!(!engine.is_topped_out());
+        assert_eq!(engine.add_garbage(2, 0, 1.0), Err(()));
+        assert!(engine.is_topped_out());
+    }
+
+    #[test]
+    fn garbage_lifts_stack_into_buffer() {
+        let mut engine = Engine::with_matrix("
+            TTT.......
+            GGGGGGGGG.
+        ".parse().unwrap());
+        engine.add_garbage(Matrix::HEIGHT - 1, 9, 0.0).unwrap();
+        assert!(!engine.is_topped_out());
+        assert_eq!(engine.matrix.cells().filter(|(_, cell, _)| *cell == Some(Color::Purple)).count(), 0);
+
+        engine.matrix[Coordinate::new(9, 0)] = Some(Color::Garbage);
+        engine.matrix[Coordinate::new(9, 1)] = Some(Color::Garbage);
+        let lines = engine.matrix.full_lines();
+        assert_eq!(lines, vec![0, 1]);
+        engine.matrix.clear_line(&lines);
+        assert_eq!(engine.matrix.cells().filter(|(_, cell, _)| *cell == Some(Color::Purple)).count(), 3);
+    }
+
+    #[test]
+    fn t_spin_double() {
+        let (matrix, cursor) = notation::parse("
+            GGG.......
+            GGtttGGGGG
+            GGGtGGGGGG
+        ").unwrap();
+        let mut engine = Engine::with_matrix(matrix);
+        engine.cursor = cursor;
+
+        engine.rotate_cursor(RotateKind::Clockwise).unwrap();
+        engine.rotate_cursor(RotateKind::CounterClockwise).unwrap();
+        engine.hard_drop();
+        assert_eq!(engine.line_clear(|_| ()), Some(LineClear {
+            lines: 2,
+            spin: Some(Spin::Full),
+            ..LineClear::default()
+        }));
+        assert_eq!(engine.line_clear(|_| ()), None);
+    }
+
+    #[test]
+    fn delays_and_initial_actions() {
+        let (matrix, cursor) = notation::parse("
+            .........i
+            .........i
+            .........i
+            GGGGGGGGGi
+        ").unwrap();
+        let mut engine = Engine::with_matrix(matrix);
+        engine.cursor = cursor;
+        engine.set_timing(Timing {
+            are: Duration::from_millis(100),
+            line_are: Duration::from_millis(200),
+            line_clear: Duration::from_millis(300),
+            lock_delay: Duration::from_millis(500),
+            gravity: None,
+            initial_actions: true,
+        });
+
+        engine.hard_drop();
+        assert_eq!(engine.line_clear(|_| ()).map(|clear| clear.lines), Some(1));
+        assert_eq!(engine.phase(), Phase::Clearing);
+        assert_eq!(engine.matrix.full_lines(), [0]);
+        engine.rotate_cursor(RotateKind::Clockwise).unwrap();
+        engine.hold().unwrap();
+
+        engine.advance_clock(Duration::from_millis(300));
+        assert_eq!(engine.phase(), Phase::Entry);
+        assert!(engine.matrix.full_lines().is_empty());
+        assert!(!engine.is_spawn_ready());
+
+        engine.advance_clock(Duration::from_millis(500));
+        assert!(engine.is_spawn_ready());
+        let queue = engine.queue().collect::<Vec<_>>();
+        engine.spawn().unwrap();
+        assert_eq!(engine.held(), Some(queue[0]));
+        assert_eq!(engine.cursor(), Some(Piece::spawned(queue[1]).rotated(RotateKind::Clockwise)));
+
+        while engine.soft_drop() {}
+        assert!(!engine.fall());
+        engine.advance_clock(Duration::from_millis(900));
+        assert!(!engine.fall());
+        engine.advance_clock(Duration::from_millis(1000));
+        assert!(engine.fall());
+    }
+
+    #[test]
+    fn lock_delay_after_ledge() {
+        let (matrix, cursor) = notation::parse("
+            ..oo......
+            ..oo......
+            GGGG......
+        ").unwrap();
+        let mut engine = Engine::with_matrix(matrix);
+        engine.cursor = cursor;
+        engine.set_timing(Timing {
+            lock_delay: Duration::from_millis(500),
+            gravity: Some(Timing::MAX_GRAVITY),
+            ..Timing::default()
+        });
+
+        assert!(!engine.fall());
+        engine.advance_clock(Duration::from_millis(400));
+        engine.move_cursor(MoveKind::Right).unwrap();
+        engine.move_cursor(MoveKind::Right).unwrap();
+        assert_eq!(engine.cursor().unwrap().cells().unwrap()[0].y, 0);
+
+        engine.advance_clock(Duration::from_millis(600));
+        assert!(!engine.fall());
+        engine.advance_clock(Duration::from_millis(1100));
+        assert!(engine.fall());
+    }
 
+    #[test]
+    fn big_pieces() {
+        let mut engine = Engine::with_matrix("
+            GGGG....GG
+            GGGG....GG
+            GGGG....GG
+            GGGG....GG
+        ".parse().unwrap());
+        engine.set_big(true);
+        engine.spawn_kind(PieceKind::O).unwrap();
+        assert_eq!(engine.cursor().unwrap().squares().unwrap().count(), 16);
+
+        engine.move_cursor(MoveKind::Left).unwrap();
+        engine.move_cursor(MoveKind::Left).unwrap();
+        assert_eq!(engine.move_cursor(MoveKind::Left), Err(()));
+        assert_eq!(engine.cursor().unwrap().cells().unwrap()[0].x, 0);
+
+        engine.move_cursor(MoveKind::Right).unwrap();
+        engine.move_cursor(MoveKind::Right).unwrap();
+        engine.hard_drop();
+        assert_eq!(engine.line_clear(|_| ()).map(|clear| clear.lines), Some(4));
+        assert_eq!(*engine.matrix(), Matrix::blank());
+
+        engine.add_garbage(1, 9, 0.0).unwrap();
+        engine.spawn_kind(PieceKind::O).unwrap();
+        engine.hard_drop();
+        let filled = |y| (0..Matrix::WIDTH).filter(|&x| engine.matrix()[Coordinate::new(x, y)].is_some()).count();
+        assert_eq!([filled(1), filled(4), filled(5)], [4, 4, 0]);
+    }
 
+    #[test]
+    fn fading_stack() {
+        let (matrix, cursor) = notation::parse("
+            ...tttGGGG
+            GGGGtGGGGG
+        ").unwrap();
+        let mut engine = Engine::with_matrix(matrix);
+        engine.cursor = cursor;
+        engine.set_visibility(Visibility::Fading(Duration::from_secs(2)));
+        engine.advance_clock(Duration::from_secs(10));
+        let alpha = |engine: &Engine| engine.cells()
+            .filter(|(_, cell, _)| cell.is_some())
+            .map(|(_, _, locked_at)| engine.cell_alpha(locked_at))
+            .collect::<Vec<_>>();
+
+        engine.hard_drop();
+        assert_eq!(engine.line_clear(|_| ()).map(|clear| clear.lines), Some(1));
+        assert!(alpha(&engine).iter().all(|alpha| *alpha == 1.0), "clears reveal the stack");
+
+        engine.advance_clock(Duration::from_secs(11));
+        assert_eq!(alpha(&engine), [0.5, 0.5, 0.5, 0.0, 0.0, 0.0, 0.0]);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,189 @@
+//! Plain-text boards for tests and puzzles. Rows run top to bottom and sit on the floor,
+//! so only the interesting bottom rows need writing:
+//!
+//! ```text
+//! ...tt.....
+//! ..tt......
+//! IIZZ..OOLL
+//! ```
+//!
+//! `.` is empty, piece letters are locked cells, `G` is garbage and
+//! lowercase letters mark the cursor piece.
+
+use std::{fmt, str::FromStr};
+use cgmath::EuclideanSpace;
+use super::{Color, Coordinate, Matrix, Offset, piece::{Piece, Kind as PieceKind, Rotation}};
+
+const EMPTY: char = '.';
+const GARBAGE: char = 'G';
+
+
+pub fn letter(kind: PieceKind) -> char {
+    match kind {
+        PieceKind::O => 'O',
+        PieceKind::I => 'I',
+        PieceKind::T => 'T',
+        PieceKind::L => 'L',
+        PieceKind::J => 'J',
+        PieceKind::S => 'S',
+        PieceKind::Z => 'Z',
+        PieceKind::Custom(shape) => shape.letter(),
+    }
+}
+
+fn color_letter(color: Color) -> char {
+    PieceKind::ALL.into_iter()
+        .find(|kind| kind.color() == color)
+        .map_or(GARBAGE, letter)
+}
+
+/// Whether `c` already stands for something on a board, so a custom piece can't take it.
+pub fn is_taken(c: char) -> bool {
+    c == EMPTY || c == GARBAGE || letter_kind(c).is_some()
+}
+
+/// The tetromino a board letter stands for, in either case.
+pub fn letter_kind(c: char) -> Option<PieceKind> {
+    PieceKind::ALL.into_iter().find(|kind| letter(*kind) == c.to_ascii_uppercase())
+}
+
+pub fn parse(text: &str) -> Result<(Matrix, Option<Piece>), String> {
+    let rows = text.lines()
+        .map(str::trim)
+        .filter(|row| !row.is_empty())
+        .collect::<Vec<_>>();
+    if rows.len() > Matrix::HEIGHT {
+        return Err(format!("{} rows, the matrix has {}", rows.len(), Matrix::HEIGHT));
+    }
+
+    let mut matrix = Matrix::blank();
+    let mut cursor: Vec<Coordinate> = Vec::new();
+    let mut cursor_kind = None;
+
+    for (y, row) in rows.iter().rev().enumerate() {
+        if row.chars().count() != Matrix::WIDTH {
+            return Err(format!("row `{row}` is not {} cells wide", Matrix::WIDTH));
+        }
+        for (x, c) in row.chars().enumerate() {
+            let coord = Coordinate::new(x, y);
+            matrix[coord] = match c {
+                EMPTY => None,
+                GARBAGE => Some(Color::Garbage),
+                c if c.is_ascii_lowercase() => {
+                    let kind = letter_kind(c).ok_or(format!("unknown cursor cell `{c}`"))?;
+                    if cursor_kind.replace(kind).is_some_and(|other| other != kind) {
+                        return Err("cursor cells of different pieces".to_owned());
+                    }
+                    cursor.push(coord);
+                    None
+                },
+                c => Some(letter_kind(c).ok_or(format!("unknown cell `{c}`"))?.color()),
+            };
+        }
+    }
+
+    let cursor = match cursor_kind {
+        Some(kind) => Some(fit_piece(kind, &cursor).ok_or("cursor cells don't form the piece")?),
+        None => None,
+    };
+    Ok((matrix, cursor))
+}
+
+/// The piece of `kind` covering exactly `cells`, if there is one.
+fn fit_piece(kind: PieceKind, cells: &[Coordinate]) -> Option<Piece> {
+    let sorted = |cells: &[Coordinate]| {
+        let mut cells = cells.to_vec();
+        cells.sort_by_key(|coord| (coord.y, coord.x));
+        cells
+    };
+    let target = sorted(cells);
+    let lowest = target.first()?.to_vec().cast::<isize>()?;
+
+    Rotation::ALL.into_iter().find_map(|rotation| {
+        let boxed = Piece { kind, rotation, position: Offset::new(0, 0), big: false };
+        let boxed_lowest = sorted(&boxed.cells()?)[0].to_vec().cast::<isize>()?;
+        let piece = boxed.moved_by(lowest - boxed_lowest);
+        (sorted(&piece.cells()?) == target).then_some(piece)
+    })
+}
+
+pub fn print(matrix: &Matrix, cursor: Option<&Piece>) -> String {
+    let cursor_cells = cursor.and_then(Piece::cells).unwrap_or_default();
+    let top = matrix.cells()
+        .filter(|(_, cell, _)| cell.is_some())
+        .map(|(coord, _, _)| coord.y)
+        .chain(cursor_cells.iter().map(|coord| coord.y))
+        .max()
+        .unwrap_or(0)
+        .min(Matrix::HEIGHT - 1);
+
+    let mut text = String::with_capacity((top + 1) * (Matrix::WIDTH + 1));
+    for y in (0..=top).rev() {
+        for x in 0..Matrix::WIDTH {
+            let coord = Coordinate::new(x, y);
+            let c = match (cursor_cells.contains(&coord), cursor) {
+                (true, Some(piece)) => letter(piece.kind).to_ascii_lowercase(),
+                _ => matrix[coord].map_or(EMPTY, color_letter),
+            };
+            text.push(c);
+        }
+        text.push('\n');
+    }
+    text
+}
+
+
+/// Rows up to the top of the stack.
+impl fmt::Display for Matrix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&print(self, None))
+    }
+}
+
+impl fmt::Debug for Matrix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\n{self}")
+    }
+}
+
+/// Parses a board without cursor cells.
+impl FromStr for Matrix {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match parse(text)? {
+            (matrix, None) => Ok(matrix),
+            (_, Some(_)) => Err("board has a cursor, use `notation::parse`".to_owned()),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn board_round_trip() {
+        let text = "\
+            ...ss.....
+            ..ss......
+            IIZZ..OOLL
+        ";
+        let (matrix, cursor) = parse(text).unwrap();
+        let cursor = cursor.unwrap();
+
+        assert_eq!(cursor.kind, PieceKind::S);
+        assert_eq!(matrix[Coordinate::new(2, 0)], Some(Color::Red));
+        assert_eq!(print(&matrix, Some(&cursor)), "...ss.....\n..ss......\nIIZZ..OOLL\n");
+        assert_eq!(matrix.to_string(), "IIZZ..OOLL\n");
+        assert_eq!(matrix.to_string().parse::<Matrix>(), Ok(matrix));
+    }
+
+    #[test]
+    fn rejects_bad_boards() {
+        assert!(parse("..........\n.....").is_err());
+        assert!("...x......".parse::<Matrix>().is_err());
+        assert!(parse("tt........\nt.........").is_err());
+    }
+}
@@ -1,16 +1,50 @@
 use cgmath::{EuclideanSpace, Vector2, Zero};
-use super::{Coordinate, Offset, Matrix, Color};
+use super::{Coordinate, Offset, Matrix, Color, piece_set::Shape};
 
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub(super) struct Piece {
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Piece {
     pub kind: Kind,
     pub position: Offset,
     pub rotation: Rotation,
+    /// Every mino is a 2x2 square and moves go two cells at a time.
+    pub big: bool,
 }
 
 impl Piece {
+    /// Cells of a tetromino. Pieces from other sets have their own count.
     pub const CELL_COUNT: usize = 4;
 
+    /// Piece as it enters the matrix: flat side down, top row flush with the ceiling.
+    pub fn spawned(kind: Kind) -> Self {
+        Self {
+            kind,
+            position: kind.spawn_position(1),
+            rotation: Rotation::N,
+            big: false,
+        }
+    }
+
+    /// Big piece as it enters, centered on the halved field.
+    pub fn spawned_big(kind: Kind) -> Self {
+        Self {
+            kind,
+            position: kind.spawn_position(2),
+            rotation: Rotation::N,
+            big: true,
+        }
+    }
+
+    /// Cells per mino side.
+    pub fn scale(&self) -> isize {
+        if self.big { 2 } else { 1 }
+    }
+
+    /// Moved by `offset` steps, which are two cells for big pieces.
+    /// Falling goes a cell at a time instead, see `Engine::hard_drop`.
+    pub fn stepped(&self, offset: Offset) -> Self {
+        self.moved_by(offset * self.scale())
+    }
+
     pub fn moved_by(&self, offset: Offset) -> Self {
         Self {
             position: self.position + offset,
@@ -18,25 +52,47 @@ impl Piece {
         }
     }
 
-    pub fn cells(&self) -> Option<[Coordinate; Self::CELL_COUNT]> {
-        
-        let offsets = self.kind.cells()
-            .map(self.rotator())
-            .map(self.positioner());
-
-        let mut coords = [Coordinate::origin(); Self::CELL_COUNT];
-
-        for (offset, coord_slot) in offsets.into_iter().zip(&mut coords) {
-            let positive_offset:Vector2<usize> = offset.cast::<usize>()?;
-            let coord = Coordinate::from_vec(positive_offset);
-            
-            if Matrix::valid_coord(coord) {
-                *coord_slot = coord;
-            } else  {
-                return None;
-            }
+    pub fn rotated(&self, rotate_kind: RotateKind) -> Self {
+        Self {
+            rotation: self.rotation.rotated(rotate_kind),
+            ..*self
         }
-        Some(coords)
+    }
+
+    pub fn cells(&self) -> Option<Vec<Coordinate>> {
+        self.kind.cells()
+            .into_iter()
+            .map(self.rotator())
+            .map(|offset| offset * self.scale())
+            .map(self.positioner())
+            .map(|offset| {
+                let positive_offset: Vector2<usize> = offset.cast::<usize>()?;
+                let coord = Coordinate::from_vec(positive_offset);
+                Matrix::valid_coord(coord).then_some(coord)
+            })
+            .collect()
+    }
+
+    /// Every matrix cell the piece covers. Same as `cells` except for big
+    /// pieces, where `cells` gives the bottom left of each square.
+    pub fn squares(&self) -> Option<impl Iterator<Item = Coordinate>> {
+        let scale = self.scale() as usize;
+        let squares = self.cells()?.into_iter().flat_map(move |corner| {
+            (0..scale * scale).map(move |i| Coordinate::new(corner.x + i % scale, corner.y + i / scale))
+        });
+        Some(squares)
+    }
+
+    /// Where SRS puts the rotation center, the convention TBP and fumen locate pieces by.
+    pub fn srs_center(&self) -> Option<Offset> {
+        let cells = self.cells()?.into_iter().map(|coord| coord.to_vec().cast::<isize>().unwrap());
+        Some(lowest(cells) - lowest(self.kind.srs_cells(self.rotation)))
+    }
+
+    pub fn from_srs_center(kind: Kind, rotation: Rotation, center: Offset) -> Option<Self> {
+        let boxed = Self { kind, rotation, position: Offset::zero(), big: false };
+        let piece = boxed.moved_by(center - boxed.srs_center()?);
+        piece.cells().map(|_| piece)
     }
 
     fn rotator(&self) ->impl Fn(Offset) -> Offset + '_ {
@@ -44,6 +100,7 @@ impl Piece {
         move |cell| cell * rotation*/
         |cell| match self.kind {
             Kind::O => cell,
+            Kind::Custom(shape) => ((cell * 2 - shape.center) * self.rotation + shape.center) / 2,
             _ => {
                 let grid_offset = self.rotation.intrinsic_offset() * (self.kind.grid_size() - 1);
                 cell * self.rotation + grid_offset
@@ -58,10 +115,12 @@ impl Piece {
 }
 
 
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub enum Kind { O, I, T, L, J, S, Z }
+/// The seven tetrominoes, or a shape from a piece set file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Kind { O, I, T, L, J, S, Z, Custom(&'static Shape) }
 
 impl Kind{
+    /// The tetrominoes, dealt unless another piece set is active.
     pub const ALL: [Self; 7] = [
                             Self::O, 
                             Self::I, 
@@ -72,21 +131,86 @@ impl Kind{
                             Self::Z
                         ];
 
-    pub fn cells(&self) -> [Offset; Piece::CELL_COUNT] {
-        match self {
+    pub fn cells(&self) -> Vec<Offset> {
+        let cells: &[(isize, isize)] = match self {
+            Self::Custom(shape) => return shape.cells.clone(),
             Self::O => &[( 1,1),( 1,2),(2,1),(2,2)],
             Self::I => &[( 0,2),( 1,2),(2,2),(3,2)],
             Self::T => &[( 0,1),( 1,1),(2,1),(1,2)],
             Self::L => &[( 0,1),( 1,1),(2,1),(2,2)],
             Self::J => &[( 0,2),( 0,1),(1,1),(2,1)],
-            Self::S => &[( 1,1),( 1,1),(1,2),(2,2)],
+            Self::S => &[( 0,1),( 1,1),(1,2),(2,2)],
             Self::Z => &[( 0,2),( 1,2),(1,1),(2,1)],
-        }.map(Offset::from)
+        };
+        cells.iter().copied().map(Offset::from).collect()
+    }
+
+    /// Where the piece enters with minos `scale` cells wide:
+    /// centered, top row flush with the ceiling.
+    fn spawn_position(&self, scale: isize) -> Offset {
+        let top = self.cells().into_iter().map(|cell| cell.y).max().unwrap();
+        let columns = Matrix::WIDTH as isize / scale;
+        let rows = Matrix::HEIGHT as isize / scale;
+        Offset::new((columns - self.grid_size()) / 2, rows - 1 - top) * scale
+    }
+
+    /// Mino offsets around the SRS rotation center. Custom shapes round
+    /// a center between cells down to the cell below left of it.
+    pub fn srs_cells(&self, rotation: Rotation) -> Vec<Offset> {
+        let cells = match self {
+            Self::Custom(shape) => return shape.cells.iter()
+                .map(|&cell| (cell * 2 - shape.center) * rotation)
+                .map(|doubled| doubled.map(|value| value.div_euclid(2)))
+                .collect(),
+            Self::I => [(-1,0),(0,0),(1,0),(2,0)],
+            Self::O => [( 0,0),(1,0),(0,1),(1,1)],
+            Self::T => [(-1,0),(0,0),(1,0),(0,1)],
+            Self::L => [(-1,0),(0,0),(1,0),(1,1)],
+            Self::J => [(-1,1),(-1,0),(0,0),(1,0)],
+            Self::S => [(-1,0),(0,0),(0,1),(1,1)],
+            Self::Z => [(-1,1),(0,1),(0,0),(1,0)],
+        };
+        cells.map(|cell| Offset::from(cell) * rotation).to_vec()
+    }
+
+    /// SRS wall kicks, tried in order when rotating `from` -> `to`.
+    /// Both tables come from the guideline offset data; the first test
+    /// is subtracted out because our rotator already turns around the box.
+    pub fn kicks(&self, from: Rotation, to: Rotation) -> Vec<Offset> {
+        let (from, to) = (self.offsets(from), self.offsets(to));
+        let base = from[0] - to[0];
+        from.into_iter().zip(to).map(|(from, to)| from - to - base).collect()
+    }
+
+    /// Offset data row for `rotation`, one entry per kick test.
+    pub(super) fn offsets(&self, rotation: Rotation) -> Vec<Offset> {
+        let table = match self {
+            Self::O => return vec![Offset::zero()],
+            Self::Custom(shape) => return shape.offsets[rotation.index()].clone(),
+            Self::I => &Self::I_OFFSETS,
+            _ => &Self::JLSTZ_OFFSETS,
+        };
+        table[rotation.index()].map(Offset::from).to_vec()
     }
 
+    const JLSTZ_OFFSETS: [[(isize, isize); 5]; 4] = [
+        [(0, 0), ( 0, 0), ( 0, 0), (0, 0), ( 0, 0)],
+        [(0, 0), ( 1, 0), ( 1,-1), (0, 2), ( 1, 2)],
+        [(0, 0), ( 0, 0), ( 0, 0), (0, 0), ( 0, 0)],
+        [(0, 0), (-1, 0), (-1,-1), (0, 2), (-1, 2)],
+    ];
+
+    const I_OFFSETS: [[(isize, isize); 5]; 4] = [
+        [( 0, 0), (-1, 0), ( 2, 0), (-1, 0), ( 2, 0)],
+        [(-1, 0), ( 0, 0), ( 0, 0), ( 0, 1), ( 0,-2)],
+        [(-1, 1), ( 1, 1), (-2, 1), ( 1, 0), (-2, 0)],
+        [( 0, 1), ( 0, 1), ( 0, 1), ( 0,-1), ( 0, 2)],
+    ];
+
     fn grid_size(&self) -> isize {
         match self {
             Self::I => 4,
+            Self::Custom(shape) => shape.box_size,
             _ => 3,
         }
     }
@@ -100,16 +224,44 @@ impl Kind{
             Self::J => Color::Blue,
             Self::S => Color::Green,
             Self::Z => Color::Red,
+            Self::Custom(shape) => shape.color,
         }
     }
 }
 
 
-#[derive(Clone,Copy, Debug, PartialEq)]
+fn lowest(cells: impl IntoIterator<Item = Offset>) -> Offset {
+    cells.into_iter().min_by_key(|cell| (cell.y, cell.x)).unwrap()
+}
+
+
+#[derive(Clone,Copy, Debug, PartialEq, Eq, Hash)]
 pub enum Rotation { N, S, E, W}
 
+#[derive(Clone,Copy, Debug, PartialEq)]
+pub enum RotateKind { Clockwise, CounterClockwise }
+
 
 impl Rotation {
+    pub const ALL: [Self; 4] = [Self::N, Self::E, Self::S, Self::W];
+
+    pub fn rotated(&self, rotate_kind: RotateKind) -> Self {
+        let step = match rotate_kind {
+            RotateKind::Clockwise => 1,
+            RotateKind::CounterClockwise => 3,
+        };
+        Self::ALL[(self.index() + step) % 4]
+    }
+
+    fn index(&self) -> usize {
+        match self {
+            Rotation::N => 0,
+            Rotation::E => 1,
+            Rotation::S => 2,
+            Rotation::W => 3,
+        }
+    }
+
     fn intrinsic_offset(&self) -> Offset {
         match self {
             Rotation::N => Offset::zero(),
@@ -144,10 +296,27 @@ mod test {
             kind: Kind::Z,
             position: Offset::new(5,6),
             rotation: Rotation::W,
+            big: false,
         };
         assert_eq!(
             z.cells(), 
-            Some([(5,6),(5,7),(6,7),(6,8)].map(Coordinate::from))
+            Some([(5,6),(5,7),(6,7),(6,8)].map(Coordinate::from).to_vec())
+        );
+    }
+
+    #[test]
+    fn srs_kicks() {
+        assert_eq!(
+            Kind::T.kicks(Rotation::N, Rotation::E),
+            [(0,0),(-1,0),(-1,1),(0,-2),(-1,-2)].map(Offset::from)
+        );
+        assert_eq!(
+            Kind::I.kicks(Rotation::N, Rotation::E),
+            [(0,0),(-2,0),(1,0),(-2,-1),(1,2)].map(Offset::from)
+        );
+        assert_eq!(
+            Kind::I.kicks(Rotation::W, Rotation::N),
+            [(0,0),(1,0),(-2,0),(1,-2),(-2,1)].map(Offset::from)
         );
     }
 }
\ No newline at end of file
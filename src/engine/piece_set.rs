@@ -0,0 +1,509 @@
+//! Piece sets other than the seven tetrominoes. A set file lists shapes,
+//! each a `[name]` header, `key = value` lines and its box drawn top row first:
+//!
+//! ```text
+//! [P]
+//! color = 80c0ff
+//! kicks = srs
+//! .XX
+//! XXX
+//! ...
+//! ```
+//!
+//! `X` is a mino and `.` empty, the box must be square. `letter` marks the
+//! piece on plain-text boards, the first letter of its name by default.
+//! Names and letters must differ from each other and from the tetrominoes'.
+//! Pieces turn around
+//! the middle of the box unless `center = x y` moves it, counted from the
+//! bottom left cell and on halves at most. `color` takes a tetromino color
+//! name or six hex digits. `kicks` is `srs` (the default), `srs_i` or `none`,
+//! or `offsets_n`, `offsets_e`, `offsets_s` and `offsets_w` each list
+//! `x,y` offset data like the SRS tables. `#` starts a comment.
+
+use std::{fmt, str::FromStr, sync::Mutex};
+use super::{Color, Offset, notation, piece::{Kind, Rotation}};
+
+const PENTOMINOES: &str = "
+[F]
+color = green
+.XX
+XX.
+.X.
+
+[F']
+letter = R
+color = red
+XX.
+.XX
+.X.
+
+[I5]
+letter = A
+color = cyan
+.....
+.....
+XXXXX
+.....
+.....
+
+[L5]
+letter = B
+color = orange
+kicks = srs_i
+....
+...X
+XXXX
+....
+
+[J5]
+letter = C
+color = blue
+kicks = srs_i
+....
+X...
+XXXX
+....
+
+[N]
+color = green
+kicks = srs_i
+....
+..XX
+XXX.
+....
+
+[N']
+letter = D
+color = red
+kicks = srs_i
+....
+XX..
+.XXX
+....
+
+[P]
+color = yellow
+.XX
+XXX
+...
+
+[Q]
+color = yellow
+XX.
+XXX
+...
+
+[T5]
+letter = E
+color = purple
+XXX
+.X.
+.X.
+
+[U]
+color = orange
+X.X
+XXX
+...
+
+[V]
+color = blue
+X..
+X..
+XXX
+
+[W]
+color = cyan
+X..
+XX.
+.XX
+
+[X]
+color = purple
+.X.
+XXX
+.X.
+
+[Y]
+color = orange
+kicks = srs_i
+....
+..X.
+XXXX
+....
+
+[Y']
+letter = H
+color = blue
+kicks = srs_i
+....
+.X..
+XXXX
+....
+
+[Z5]
+letter = K
+color = red
+XX.
+.X.
+.XX
+
+[S5]
+letter = M
+color = green
+.XX
+.X.
+XX.
+";
+
+/// Small pieces for beginners.
+const EASY: &str = "
+[M]
+color = yellow
+kicks = none
+X
+
+[I3]
+letter = A
+color = cyan
+...
+XXX
+...
+
+[V3]
+color = orange
+X.
+XX
+";
+
+
+/// A piece shape from a set file, see `Kind::Custom`.
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub struct Shape {
+    pub name: String,
+    letter: char,
+    pub color: Color,
+    /// Minos in the box, x right and y up from its bottom left cell.
+    pub(super) cells: Vec<Offset>,
+    pub(super) box_size: isize,
+    /// Twice the rotation center, which may sit between cells.
+    pub(super) center: Offset,
+    /// Kick offset data per rotation, in `Rotation::ALL` order.
+    pub(super) offsets: [Vec<Offset>; 4],
+}
+
+impl Shape {
+    /// Letter for plain-text boards.
+    pub fn letter(&self) -> char {
+        self.letter
+    }
+
+    fn parse(name: &str, lines: &[&str]) -> Result<Self, String> {
+        if name.is_empty() {
+            return Err("unnamed piece".to_owned());
+        }
+        let mut letter = name.chars().next().map(|c| c.to_ascii_uppercase());
+        let mut color = None;
+        let mut center = None;
+        let mut offsets = Rotation::ALL.map(|rotation| Kind::T.offsets(rotation));
+        let mut rows = Vec::new();
+
+        for line in lines {
+            let Some((key, value)) = line.split_once('=') else {
+                rows.push(*line);
+                continue;
+            };
+            let (key, value) = (key.trim(), value.trim());
+            let bad_value = || format!("bad value for `{key}`");
+
+            match key {
+                "letter" => letter = Some(parse_letter(value).ok_or_else(bad_value)?),
+                "color" => color = Some(parse_color(value).ok_or_else(bad_value)?),
+                "center" => center = Some(parse_center(value).ok_or_else(bad_value)?),
+                "kicks" => {
+                    let kind = match value {
+                        "srs" => Kind::T,
+                        "srs_i" => Kind::I,
+                        "none" => Kind::O,
+                        _ => return Err(bad_value()),
+                    };
+                    offsets = Rotation::ALL.map(|rotation| kind.offsets(rotation));
+                },
+                _ => {
+                    let index = ["offsets_n", "offsets_e", "offsets_s", "offsets_w"]
+                        .iter()
+                        .position(|known| *known == key)
+                        .ok_or_else(|| format!("unknown key `{key}`"))?;
+                    offsets[index] = value.split_whitespace()
+                        .map(|pair| parse_offset(pair).ok_or_else(bad_value))
+                        .collect::<Result<_, _>>()?;
+                },
+            }
+        }
+
+        let box_size = rows.len();
+        if box_size == 0 || rows.iter().any(|row| row.chars().count() != box_size) {
+            return Err("the box must be square".to_owned());
+        }
+        let mut cells = Vec::new();
+        for (y, row) in rows.iter().rev().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                match c {
+                    'X' => cells.push(Offset::new(x as isize, y as isize)),
+                    '.' => {},
+                    c => return Err(format!("unexpected `{c}` in the box")),
+                }
+            }
+        }
+        if cells.is_empty() {
+            return Err("no minos".to_owned());
+        }
+        if offsets.iter().any(|row| row.is_empty() || row.len() != offsets[0].len()) {
+            return Err("offset rows must have the same number of tests".to_owned());
+        }
+
+        let box_size = box_size as isize;
+        Ok(Shape {
+            name: name.to_owned(),
+            letter: letter.filter(char::is_ascii_alphabetic).ok_or("missing `letter`")?,
+            color: color.ok_or("missing `color`")?,
+            cells,
+            box_size,
+            center: center.unwrap_or(Offset::new(box_size - 1, box_size - 1)),
+            offsets,
+        })
+    }
+}
+
+const COLOR_NAMES: [(&str, Color); 8] = [
+    ("yellow", Color::Yellow),
+    ("cyan", Color::Cyan),
+    ("purple", Color::Purple),
+    ("orange", Color::Orange),
+    ("blue", Color::Blue),
+    ("green", Color::Green),
+    ("red", Color::Red),
+    ("garbage", Color::Garbage),
+];
+
+fn parse_letter(text: &str) -> Option<char> {
+    let mut chars = text.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_alphabetic() => Some(c.to_ascii_uppercase()),
+        _ => None,
+    }
+}
+
+fn parse_color(text: &str) -> Option<Color> {
+    if let Some((_, color)) = COLOR_NAMES.iter().find(|(name, _)| *name == text) {
+        return Some(*color);
+    }
+    let rgb = u32::from_str_radix(text, 16).ok().filter(|_| text.len() == 6)?;
+    Some(Color::Rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8))
+}
+
+/// Doubled, so centers between cells stay whole. Turning around the
+/// center only keeps minos on the grid when both halves agree.
+fn parse_center(text: &str) -> Option<Offset> {
+    let doubled = text.split_whitespace()
+        .map(|value| value.parse::<f32>().ok().map(|value| value * 2.0).filter(|value| value.fract() == 0.0))
+        .collect::<Option<Vec<_>>>()?;
+    match doubled[..] {
+        [x, y] if (x as isize - y as isize) % 2 == 0 => Some(Offset::new(x as isize, y as isize)),
+        _ => None,
+    }
+}
+
+/// Shapes live as long as the game does, so pieces can stay `Copy`.
+/// Equal shapes share one copy, however often a set gets parsed.
+fn intern(shape: Shape) -> &'static Shape {
+    static SHAPES: Mutex<Vec<&'static Shape>> = Mutex::new(Vec::new());
+    let mut shapes = SHAPES.lock().unwrap();
+    if let Some(known) = shapes.iter().find(|known| ***known == shape) {
+        return known;
+    }
+    let shape = Box::leak(Box::new(shape));
+    shapes.push(shape);
+    shape
+}
+
+fn parse_offset(text: &str) -> Option<Offset> {
+    let (x, y) = text.split_once(',')?;
+    Some(Offset::new(x.trim().parse().ok()?, y.trim().parse().ok()?))
+}
+
+
+/// The kinds the randomizer deals from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PieceSet(Vec<Kind>);
+
+impl Default for PieceSet {
+    fn default() -> Self {
+        PieceSet(Kind::ALL.to_vec())
+    }
+}
+
+impl PieceSet {
+    /// Sets that come with the game.
+    pub const NAMES: [&'static str; 3] = ["tetrominoes", "pentominoes", "easy"];
+
+    pub fn named(name: &str) -> Option<Self> {
+        let text = match name {
+            "tetrominoes" => return Some(Self::default()),
+            "pentominoes" => PENTOMINOES,
+            "easy" => EASY,
+            _ => return None,
+        };
+        Some(text.parse().expect("Fail to parse a bundled piece set"))
+    }
+
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self, String> {
+        std::fs::read_to_string(path)
+            .map_err(|error| error.to_string())?
+            .parse()
+    }
+
+    pub fn kinds(&self) -> &[Kind] {
+        &self.0
+    }
+}
+
+/// The set file of the custom shapes, the tetrominoes have none.
+impl fmt::Display for PieceSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for kind in &self.0 {
+            let Kind::Custom(shape) = kind else {
+                continue;
+            };
+            writeln!(f, "[{}]", shape.name)?;
+            writeln!(f, "letter = {}", shape.letter)?;
+            match (COLOR_NAMES.iter().find(|(_, color)| *color == shape.color), shape.color) {
+                (Some((name, _)), _) => writeln!(f, "color = {name}")?,
+                (None, Color::Rgb(r, g, b)) => writeln!(f, "color = {r:02x}{g:02x}{b:02x}")?,
+                (None, _) => unreachable!("every other color has a name"),
+            }
+            writeln!(f, "center = {} {}", shape.center.x as f32 / 2.0, shape.center.y as f32 / 2.0)?;
+            for (key, offsets) in ["offsets_n", "offsets_e", "offsets_s", "offsets_w"].iter().zip(&shape.offsets) {
+                let offsets = offsets.iter().map(|offset| format!("{},{}", offset.x, offset.y)).collect::<Vec<_>>();
+                writeln!(f, "{key} = {}", offsets.join(" "))?;
+            }
+            for y in (0..shape.box_size).rev() {
+                let row = (0..shape.box_size)
+                    .map(|x| if shape.cells.contains(&Offset::new(x, y)) { 'X' } else { '.' })
+                    .collect::<String>();
+                writeln!(f, "{row}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for PieceSet {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut sections: Vec<(&str, Vec<&str>)> = Vec::new();
+
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            match line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+                Some(name) => sections.push((name.trim(), Vec::new())),
+                None => sections.last_mut()
+                    .ok_or_else(|| format!("`{line}` before the first [piece]"))?
+                    .1.push(line),
+            }
+        }
+        if sections.is_empty() {
+            return Err("no pieces".to_owned());
+        }
+
+        let shapes = sections.into_iter()
+            .map(|(name, lines)| Shape::parse(name, &lines).map_err(|error| format!("[{name}]: {error}")))
+            .collect::<Result<Vec<_>, _>>()?;
+        for (index, shape) in shapes.iter().enumerate() {
+            let earlier = &shapes[..index];
+            let tetromino = Kind::ALL.iter().any(|kind| notation::letter(*kind).to_string() == shape.name);
+            if tetromino || earlier.iter().any(|other| other.name == shape.name) {
+                return Err(format!("[{}]: the name is taken", shape.name));
+            }
+            if notation::is_taken(shape.letter) || earlier.iter().any(|other| other.letter == shape.letter) {
+                return Err(format!("[{}]: letter `{}` is taken", shape.name, shape.letter));
+            }
+        }
+        Ok(PieceSet(shapes.into_iter().map(|shape| Kind::Custom(intern(shape))).collect()))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::engine::{Engine, Matrix, piece::{Piece, RotateKind}};
+
+    #[test]
+    fn bundled_sets() {
+        let pentominoes = PieceSet::named("pentominoes").unwrap();
+        assert_eq!(pentominoes.kinds().len(), 18);
+
+        for kind in pentominoes.kinds().iter().chain(PieceSet::named("easy").unwrap().kinds()) {
+            let spawned = Piece::spawned(*kind);
+            let top = spawned.cells().unwrap().into_iter().map(|coord| coord.y).max();
+            assert_eq!(top, Some(Matrix::HEIGHT - 1), "{kind:?}");
+
+            let mut piece = spawned;
+            for _ in Rotation::ALL {
+                piece = Matrix::blank().rotated(&piece, RotateKind::Clockwise).unwrap();
+                assert_eq!(piece.cells().unwrap().len(), kind.cells().len());
+            }
+            assert_eq!(piece.cells(), spawned.cells(), "{kind:?}");
+        }
+
+        let mut engine = Engine::with_seed(3);
+        engine.set_piece_set(pentominoes.clone());
+        engine.spawn().unwrap();
+        let dealt = engine.cursor().map(|cursor| cursor.kind).into_iter().chain(engine.queue());
+        assert!(dealt.into_iter().all(|kind| pentominoes.kinds().contains(&kind)));
+    }
+
+    #[test]
+    fn custom_shapes() {
+        let set = "
+            # an S turning around its middle mino
+            [s]
+            letter = k
+            color = 102030
+            center = 1 1
+            offsets_n = 0,0 0,1
+            offsets_e = 0,0 1,1
+            offsets_s = 0,0 0,1
+            offsets_w = 0,0 -1,1
+            ...
+            .XX
+            XX.
+        ".parse::<PieceSet>().unwrap();
+        let Kind::Custom(shape) = set.kinds()[0] else {
+            panic!("expected a custom shape");
+        };
+        assert_eq!(shape.color, Color::Rgb(0x10, 0x20, 0x30));
+        assert_eq!(shape.letter(), 'K');
+        assert_eq!(set.kinds()[0].kicks(Rotation::N, Rotation::E), [(0, 0), (-1, 0)].map(Offset::from));
+
+        assert_eq!(set.to_string().parse::<PieceSet>(), Ok(set.clone()));
+        let pentominoes = PieceSet::named("pentominoes").unwrap();
+        assert_eq!(pentominoes.to_string().parse::<PieceSet>(), Ok(pentominoes));
+
+        assert!("[a]\ncolor = red\nX.\n".parse::<PieceSet>().is_err());
+        assert!("[a]\ncolor = red\ncenter = 0.5 1\nX\n".parse::<PieceSet>().is_err());
+        assert!("X\n".parse::<PieceSet>().is_err());
+
+        let dot = "color = red\nX\n";
+        assert!(format!("[s]\n{dot}").parse::<PieceSet>().is_err(), "S is a tetromino letter");
+        assert!(format!("[I]\nletter = a\n{dot}").parse::<PieceSet>().is_err(), "I is a tetromino name");
+        assert!(format!("[a]\n{dot}[a]\nletter = b\n{dot}").parse::<PieceSet>().is_err());
+        assert!(format!("[a]\n{dot}[b]\nletter = a\n{dot}").parse::<PieceSet>().is_err());
+        assert!(format!("[a]\n{dot}[b]\n{dot}").parse::<PieceSet>().is_ok());
+    }
+}
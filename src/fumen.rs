@@ -0,0 +1,500 @@
+//! Fumen v115 strings, the usual way boards and setups are shared.
+//! Each page is a field diff against the previous page, a piece action and an optional comment,
+//! all packed into 64 character base64-like digits.
+
+use crate::engine::{Color, Coordinate, Engine, Matrix, Offset, piece::{Piece, Kind as PieceKind, Rotation}};
+
+const PREFIXES: [&str; 3] = ["v115@", "m115@", "d115@"];
+const DIGITS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const COMMENT_CHARS: &[u8] = b" !\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~";
+const COMMENT_BASE: usize = COMMENT_CHARS.len() + 1;
+const MAX_COMMENT_LEN: usize = 4095;
+
+/// Fumen rows: 23 playfield rows above one garbage row.
+const FIELD_TOP: usize = 23;
+const FIELD_ROWS: usize = FIELD_TOP + 1;
+const FIELD_BLOCKS: usize = FIELD_ROWS * Matrix::WIDTH;
+/// Diff value meaning "cell unchanged".
+const SAME: usize = 8;
+const GRAY: u8 = 8;
+
+
+#[derive(Clone, PartialEq)]
+pub struct Page {
+    pub matrix: Matrix,
+    pub piece: Option<Piece>,
+    /// Comments carry over to later pages until changed.
+    pub comment: String,
+    /// The piece locks and full lines clear before the next page.
+    pub lock: bool,
+    /// Push the garbage row into the field after locking.
+    pub rise: bool,
+    pub mirror: bool,
+}
+
+impl Page {
+    pub fn new(matrix: Matrix) -> Self {
+        Page {
+            matrix,
+            piece: None,
+            comment: String::new(),
+            lock: true,
+            rise: false,
+            mirror: false,
+        }
+    }
+
+    /// The matrix and the piece in play, ready to share.
+    /// Fumen only knows the tetrominoes, other pieces are left out.
+    pub fn of(engine: &Engine) -> Self {
+        Page {
+            piece: engine.cursor().filter(|piece| PieceKind::ALL.contains(&piece.kind)),
+            ..Page::new(*engine.matrix())
+        }
+    }
+}
+
+
+pub fn decode(text: &str) -> Result<Vec<Page>, String> {
+    let text = text.chars().filter(|c| !c.is_whitespace()).collect::<String>();
+    let data = PREFIXES.iter()
+        .find_map(|prefix| text.strip_prefix(prefix))
+        .ok_or("not a v115 fumen")?;
+    let digits = data.bytes()
+        .filter(|&c| c != b'?')
+        .map(|c| DIGITS.iter().position(|&digit| digit == c).ok_or(format!("invalid character '{}'", c as char)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut reader = Reader { digits, at: 0 };
+    let mut field = Field::blank();
+    let mut comment = String::new();
+    let mut repeat = 0;
+    let mut pages = Vec::new();
+
+    while reader.at < reader.digits.len() {
+        if repeat > 0 {
+            repeat -= 1;
+        } else if !field.read_diff(&mut reader)? {
+            repeat = reader.poll(1)?;
+        }
+
+        let mut action = reader.poll(3)?;
+        let mut take = |base: usize| {
+            let value = action % base;
+            action /= base;
+            value
+        };
+        let (kind, rotation, position) = (take(8), take(4), take(FIELD_BLOCKS));
+        let [rise, mirror, _color, has_comment, unlocked] = [(); 5].map(|_| take(2) == 1);
+
+        if has_comment {
+            comment = reader.comment()?;
+        }
+
+        let piece = decode_piece(kind, rotation, position);
+        pages.push(Page {
+            matrix: field.to_matrix()?,
+            piece,
+            comment: comment.clone(),
+            lock: !unlocked,
+            rise,
+            mirror,
+        });
+
+        if !unlocked {
+            field.lock(piece.as_ref(), rise, mirror)?;
+        }
+    }
+    Ok(pages)
+}
+
+pub fn encode(pages: &[Page]) -> String {
+    let mut writer = Vec::new();
+    let mut previous = Field::blank();
+    let mut comment = "";
+    let mut repeat_at: Option<usize> = None;
+
+    for (index, page) in pages.iter().enumerate() {
+        let mut field = Field::from_matrix(&page.matrix);
+        field.0[0] = previous.0[0];
+
+        let (changed, diff) = previous.diff(&field);
+        match repeat_at {
+            _ if changed => {
+                writer.extend(diff);
+                repeat_at = None;
+            },
+            Some(at) if writer[at] < 63 => writer[at] += 1,
+            _ => {
+                writer.extend(diff);
+                repeat_at = Some(writer.len());
+                writer.push(0);
+            },
+        }
+
+        let has_comment = page.comment != comment;
+        let flags = [
+            !page.lock,
+            has_comment,
+            index == 0,
+            page.mirror,
+            page.rise,
+        ];
+        let (kind, rotation, position) = page.piece.map_or((0, 0, 0), encode_piece);
+        let action = flags.into_iter().fold(0, |value, flag| value * 2 + usize::from(flag));
+        let action = ((action * FIELD_BLOCKS + position) * 4 + rotation) * 8 + kind;
+        push(&mut writer, action, 3);
+
+        if has_comment {
+            push_comment(&mut writer, &page.comment);
+            comment = &page.comment;
+        }
+
+        if page.lock {
+            // Pages built from our matrices always fit, so locking can't fail here.
+            let _ = field.lock(page.piece.as_ref(), page.rise, page.mirror);
+        }
+        previous = field;
+    }
+
+    let data = writer.into_iter().map(|digit| DIGITS[digit] as char).collect::<String>();
+    let mut text = String::from(PREFIXES[0]);
+    let (head, tail) = data.split_at(data.len().min(42));
+    text.push_str(head);
+    for chunk in tail.as_bytes().chunks(47) {
+        text.push('?');
+        text.push_str(std::str::from_utf8(chunk).unwrap());
+    }
+    text
+}
+
+
+fn kind_code(kind: PieceKind) -> u8 {
+    match kind {
+        PieceKind::I => 1,
+        PieceKind::L => 2,
+        PieceKind::O => 3,
+        PieceKind::Z => 4,
+        PieceKind::T => 5,
+        PieceKind::J => 6,
+        PieceKind::S => 7,
+        PieceKind::Custom(_) => GRAY,
+    }
+}
+
+fn code_kind(code: u8) -> Option<PieceKind> {
+    PieceKind::ALL.into_iter().find(|kind| kind_code(*kind) == code)
+}
+
+fn color_code(color: Color) -> u8 {
+    PieceKind::ALL.into_iter()
+        .find(|kind| kind.color() == color)
+        .map_or(GRAY, kind_code)
+}
+
+fn code_color(code: u8) -> Option<Color> {
+    match code {
+        0 => None,
+        GRAY => Some(Color::Garbage),
+        code => code_kind(code).map(|kind| kind.color()),
+    }
+}
+
+fn rotation_code(rotation: Rotation) -> usize {
+    match rotation {
+        Rotation::S => 0,
+        Rotation::E => 1,
+        Rotation::N => 2,
+        Rotation::W => 3,
+    }
+}
+
+/// Fumen stores a few pieces one cell off their SRS center.
+fn center_shift(kind: PieceKind, rotation: Rotation) -> Offset {
+    match (kind, rotation) {
+        (PieceKind::O, Rotation::W) => Offset::new(1, -1),
+        (PieceKind::O, Rotation::S) => Offset::new(1, 0),
+        (PieceKind::O, Rotation::N) => Offset::new(0, -1),
+        (PieceKind::I, Rotation::S) => Offset::new(1, 0),
+        (PieceKind::I, Rotation::W) => Offset::new(0, -1),
+        (PieceKind::S, Rotation::N) => Offset::new(0, -1),
+        (PieceKind::S, Rotation::E) => Offset::new(-1, 0),
+        (PieceKind::Z, Rotation::N) => Offset::new(0, -1),
+        (PieceKind::Z, Rotation::W) => Offset::new(1, 0),
+        _ => Offset::new(0, 0),
+    }
+}
+
+fn decode_piece(kind: usize, rotation: usize, position: usize) -> Option<Piece> {
+    let kind = code_kind(kind as u8)?;
+    let rotation = Rotation::ALL.into_iter().find(|r| rotation_code(*r) == rotation)?;
+    let stored = Offset::new(
+        (position % Matrix::WIDTH) as isize,
+        (FIELD_TOP - 1) as isize - (position / Matrix::WIDTH) as isize,
+    );
+    Piece::from_srs_center(kind, rotation, stored + center_shift(kind, rotation))
+}
+
+fn encode_piece(piece: Piece) -> (usize, usize, usize) {
+    let stored = piece.srs_center().unwrap() - center_shift(piece.kind, piece.rotation);
+    let row = (FIELD_TOP - 1) as isize - stored.y;
+    let position = row * Matrix::WIDTH as isize + stored.x;
+    (kind_code(piece.kind) as usize, rotation_code(piece.rotation), position as usize)
+}
+
+
+struct Reader {
+    digits: Vec<usize>,
+    at: usize,
+}
+
+impl Reader {
+    /// Little endian number spread over `count` digits.
+    fn poll(&mut self, count: usize) -> Result<usize, String> {
+        let digits = self.digits.get(self.at..self.at + count).ok_or("fumen ends early")?;
+        self.at += count;
+        Ok(digits.iter().rev().fold(0, |value, digit| value * 64 + digit))
+    }
+
+    fn comment(&mut self) -> Result<String, String> {
+        let len = self.poll(2)?;
+        let mut escaped = String::with_capacity(len + 3);
+        for _ in 0..len.div_ceil(4) {
+            let mut value = self.poll(5)?;
+            for _ in 0..4 {
+                let c = COMMENT_CHARS.get(value % COMMENT_BASE).ok_or("invalid comment character")?;
+                escaped.push(*c as char);
+                value /= COMMENT_BASE;
+            }
+        }
+        escaped.truncate(len);
+        Ok(unescape(&escaped))
+    }
+}
+
+fn push(writer: &mut Vec<usize>, mut value: usize, count: usize) {
+    for _ in 0..count {
+        writer.push(value % 64);
+        value /= 64;
+    }
+}
+
+fn push_comment(writer: &mut Vec<usize>, comment: &str) {
+    let escaped = escape(comment);
+    let escaped = &escaped.as_bytes()[..escaped.len().min(MAX_COMMENT_LEN)];
+    push(writer, escaped.len(), 2);
+    for chunk in escaped.chunks(4) {
+        let value = chunk.iter().rev().fold(0, |value, c| {
+            value * COMMENT_BASE + COMMENT_CHARS.iter().position(|known| known == c).unwrap_or(0)
+        });
+        push(writer, value, 5);
+    }
+}
+
+/// JavaScript `escape`, which fumen applies to comments.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            c if c.is_ascii_alphanumeric() || "@*_+-./".contains(c) => escaped.push(c),
+            c if (c as u32) < 0x100 => escaped.push_str(&format!("%{:02X}", c as u32)),
+            c => {
+                for unit in c.encode_utf16(&mut [0; 2]) {
+                    escaped.push_str(&format!("%u{unit:04X}"));
+                }
+            },
+        }
+    }
+    escaped
+}
+
+fn unescape(text: &str) -> String {
+    let mut units = Vec::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let hex = |digits: &str| u16::from_str_radix(digits, 16).ok();
+        let escaped = match rest.as_bytes() {
+            [b'%', b'u', ..] => rest.get(2..6).and_then(hex).map(|unit| (unit, 6)),
+            [b'%', ..] => rest.get(1..3).and_then(hex).map(|unit| (unit, 3)),
+            _ => None,
+        };
+        match escaped {
+            Some((unit, len)) => {
+                units.push(unit);
+                rest = &rest[len..];
+            },
+            None => {
+                units.extend(c.encode_utf16(&mut [0; 2]).iter());
+                rest = &rest[c.len_utf8()..];
+            },
+        }
+    }
+    String::from_utf16_lossy(&units)
+}
+
+
+/// Fumen's own field: row 0 is the garbage row, row `y + 1` is matrix row `y`.
+#[derive(Clone)]
+struct Field([[u8; Matrix::WIDTH]; FIELD_ROWS]);
+
+impl Field {
+    fn blank() -> Self {
+        Field([[0; Matrix::WIDTH]; FIELD_ROWS])
+    }
+
+    fn from_matrix(matrix: &Matrix) -> Self {
+        let mut field = Self::blank();
+        for (coord, cell, _) in matrix.cells() {
+            field.0[coord.y + 1][coord.x] = cell.map_or(0, color_code);
+        }
+        field
+    }
+
+    fn to_matrix(&self) -> Result<Matrix, String> {
+        if self.0[Matrix::HEIGHT + 1..].iter().flatten().any(|&code| code != 0) {
+            return Err(format!("field is taller than {} rows", Matrix::HEIGHT));
+        }
+        let mut matrix = Matrix::blank();
+        for (y, row) in self.0[1..=Matrix::HEIGHT].iter().enumerate() {
+            for (x, &code) in row.iter().enumerate() {
+                matrix[Coordinate::new(x, y)] = code_color(code);
+            }
+        }
+        Ok(matrix)
+    }
+
+    /// Reading order of the diff: top row first, garbage row last.
+    fn blocks() -> impl Iterator<Item = (usize, usize)> {
+        (0..FIELD_ROWS).rev().flat_map(|row| (0..Matrix::WIDTH).map(move |x| (row, x)))
+    }
+
+    /// Applies a run-length encoded diff, returns false for the "nothing changed" marker.
+    fn read_diff(&mut self, reader: &mut Reader) -> Result<bool, String> {
+        let mut blocks = Self::blocks();
+        let mut read = 0;
+        let mut changed = true;
+        while read < FIELD_BLOCKS {
+            let run = reader.poll(2)?;
+            let (diff, len) = (run / FIELD_BLOCKS, run % FIELD_BLOCKS + 1);
+            if diff == SAME && len == FIELD_BLOCKS {
+                changed = false;
+            }
+            for (row, x) in blocks.by_ref().take(len) {
+                let cell = &mut self.0[row][x];
+                *cell = (*cell as usize + diff).checked_sub(SAME).ok_or("invalid field diff")? as u8;
+            }
+            read += len;
+        }
+        Ok(changed)
+    }
+
+    fn diff(&self, next: &Field) -> (bool, Vec<usize>) {
+        let mut writer = Vec::new();
+        let mut runs: Vec<(usize, usize)> = Vec::new();
+        for (row, x) in Self::blocks() {
+            let diff = next.0[row][x] as usize + SAME - self.0[row][x] as usize;
+            match runs.last_mut() {
+                Some((last, len)) if *last == diff && *len < FIELD_BLOCKS => *len += 1,
+                _ => runs.push((diff, 1)),
+            }
+        }
+        let changed = runs != [(SAME, FIELD_BLOCKS)];
+        for (diff, len) in runs {
+            push(&mut writer, diff * FIELD_BLOCKS + len - 1, 2);
+        }
+        (changed, writer)
+    }
+
+    fn lock(&mut self, piece: Option<&Piece>, rise: bool, mirror: bool) -> Result<(), String> {
+        if let Some(piece) = piece {
+            for coord in piece.cells().ok_or("piece outside the field")? {
+                let row = self.0.get_mut(coord.y + 1).ok_or("piece above the field")?;
+                row[coord.x] = kind_code(piece.kind);
+            }
+        }
+
+        let kept = self.0[1..]
+            .iter()
+            .filter(|row| row.contains(&0))
+            .copied()
+            .collect::<Vec<_>>();
+        self.0[1..].fill([0; Matrix::WIDTH]);
+        self.0[1..1 + kept.len()].copy_from_slice(&kept);
+
+        if rise {
+            self.0.copy_within(0..FIELD_ROWS - 1, 1);
+            self.0[0] = [0; Matrix::WIDTH];
+        }
+        if mirror {
+            for row in &mut self.0[1..] {
+                row.reverse();
+            }
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn blank_page() {
+        let pages = decode("v115@vhAAgH").unwrap();
+        assert_eq!(pages.len(), 1);
+        assert!(pages[0].matrix == Matrix::blank() && pages[0].piece.is_none());
+        assert_eq!(encode(&pages), "v115@vhAAgH");
+    }
+
+    #[test]
+    fn decodes_known_pages() {
+        // An I filling the well under a comment, a T on the I's remains
+        // with the comment carried over, then a gray cell and a new comment.
+        let pages = decode("v115@bhI8KepIYFAooMDEPBAAAvhAVwBbhA8SeAAPDAF2aCA").unwrap();
+        assert_eq!(pages.len(), 3);
+
+        assert!(pages[0].matrix == "GGGGGGGGG.".parse().unwrap());
+        assert_eq!(pages[0].piece, Piece::from_srs_center(PieceKind::I, Rotation::E, Offset::new(9, 2)));
+        assert_eq!(pages[0].comment, "hello");
+
+        assert!(pages[1].matrix == "
+            .........I
+            .........I
+            .........I
+        ".parse().unwrap());
+        assert_eq!(pages[1].piece, Piece::from_srs_center(PieceKind::T, Rotation::N, Offset::new(4, 0)));
+        assert_eq!(pages[1].comment, "hello");
+
+        assert!(pages[2].matrix == "
+            .........I
+            ....T....I
+            G..TTT...I
+        ".parse().unwrap());
+        assert_eq!(pages[2].piece, None);
+        assert_eq!(pages[2].comment, "end");
+        assert!(pages.iter().all(|page| page.lock && !page.rise && !page.mirror));
+    }
+
+    #[test]
+    fn pages_round_trip() {
+        let mut matrix = Matrix::blank();
+        for x in 0..9 {
+            matrix[Coordinate::new(x, 0)] = Some(Color::Orange);
+        }
+        let i = Piece { kind: PieceKind::I, rotation: Rotation::E, position: Offset::new(7, 0), big: false };
+        let o = Piece { kind: PieceKind::O, rotation: Rotation::W, position: Offset::new(2, 3), big: false };
+
+        let first = Page { piece: Some(i), comment: "100% dig ü".to_owned(), ..Page::new(matrix) };
+        let mut after = matrix;
+        after.place(&i);
+        let lines = after.full_lines();
+        after.clear_line(&lines);
+        let second = Page { piece: Some(o), comment: first.comment.clone(), ..Page::new(after) };
+        let third = Page { lock: false, comment: "next".to_owned(), ..Page::new(Matrix::blank()) };
+
+        let pages = vec![first, second, third];
+        let decoded = decode(&encode(&pages)).unwrap();
+        assert!(decoded == pages);
+    }
+}
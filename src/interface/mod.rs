@@ -1,14 +1,30 @@
-use std::time::Duration;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
 use cgmath::{Vector2, EuclideanSpace, ElementWise, Point2};
-use sdl2::{pixels::Color, event::Event, rect::{Rect, Point}, render::Canvas, video::Window, keyboard::Keycode};
+use sdl2::{pixels::Color, event::Event, rect::{Rect, Point}, render::{BlendMode, Canvas}, video::Window, keyboard::Keycode, Sdl};
 use self::render_trait::ScreenColor;
-use crate::engine::{Engine, Matrix, Color as SemanticColor, MoveKind};
+use crate::engine::{Engine, Matrix, Color as SemanticColor, MoveKind, Visibility, piece::{Piece, Kind as PieceKind, RotateKind}};
+use crate::bot::{Action, Bot, Opponent, perfect_clear};
+use crate::fumen;
+use crate::mode::{Game, last_replay_path, replay::{Play, Replay}};
+use crate::net::{self, Inputs, Session, Link};
+use crate::spectate::{self, Server, View};
+use crate::versus::Versus;
 
 mod render_trait;
+mod text;
 const INIT_SIZE: Vector2<u32> = Vector2::new(1024,1024);
 const BACKGROUND_COLOR: Color = Color::RGB(0x10,0x10,0x18);
 const PLACEHOLDER_1: Color = Color::RGB(0x66, 0x77, 0x77);
 const PLACEHOLDER_2: Color = Color::RGB(0x77, 0x88, 0x88);
+const BOT_INPUT_DELAY: Duration = Duration::from_millis(60);
+const OVERLAY_ALPHA: u8 = 0x70;
+const PERFECT_CLEAR_HEIGHT: usize = 4;
+/// Placements the hint may try before giving up on a position.
+const PERFECT_CLEAR_BUDGET: usize = 20_000;
+/// Characters that fit a line of the score panel.
+const PANEL_COLUMNS: u32 = 14;
 
 struct Tick;
 struct LockTick;
@@ -16,86 +32,404 @@ struct SoftDropTick;
 struct Sleep(Duration);
 
 
-pub fn run(mut engine: Engine) {
+/// Who plays the game shown by `run`.
+pub enum Driver {
+    Keyboard,
+    /// Attract demo, restarting whenever the bot tops out.
+    Bot(Bot),
+    /// Plays back a recorded game, keys ignored.
+    Replay(Replay),
+}
+
+/// Runs a game window until closed. `spectators` get the game streamed.
+pub fn run(mut game: Game, driver: Driver, mut spectators: Option<Server>) {
     let sdl = sdl2::init().expect("Fail to init SDL2");
 
     let event_subsys = sdl.event().expect("faled to activate event subsystem");
     event_subsys.register_custom_event::<Tick>().unwrap();
     event_subsys.register_custom_event::<LockTick>().unwrap();
 
-    let mut canvas = {
-        let video = sdl.video().expect("Fail to acqure display");
-        
-        let window = video
-            .window("Tetris", INIT_SIZE.x, INIT_SIZE.y)
-            .position_centered()
-            .resizable()
-            .build()
-            .expect("Fail to create window");
-
-        window
-            .into_canvas()
-            .accelerated()
-            .present_vsync()
-            .build()
-            .expect("Fail to render canvas")
-    };
+    let mut canvas = open_window(&sdl, INIT_SIZE);
 
     let mut events = sdl.event_pump().expect("Fail to get event loop");
     
     event_subsys.push_custom_event(Tick).unwrap();
     event_subsys.push_custom_event(LockTick).unwrap();
 
-    let mut redraw: bool = true; //flag means redraw screen
-    let mut lockdown = false;
+    let mut start = Instant::now();
+    let mut last_drop = Instant::now();
+    let mut last_bot_input = Instant::now();
+    let mut plan: Vec<Action> = Vec::new();
+    let mut replay = match &driver {
+        Driver::Replay(replay) => replay.plays.iter().rev().copied().collect(),
+        _ => Vec::new(),
+    };
+    let mut show_hint = false;
+    let mut hint = Hint::new();
 
-    //todo: ticks, rotation, place piece on each other
-    
     loop {
+        let mut plays = Vec::new();
+        let mut restart = None;
+
         for event in events.poll_iter() {
             match event {
                 Event::Quit { .. } =>return,
                 Event::User { .. } if event.as_user_event_type::<Tick>().is_some() => {
                     println!("tick ev");
-                    redraw = true;
-                    lockdown = true;
                 },
                 Event::User { .. } if event.as_user_event_type::<LockTick>().is_some() => {
                     println!("lock tick  ev");
-                    redraw = true;
                 },
-                Event::KeyDown { keycode: Some(key) , ..} => {
-                    if let Ok(input) = Input::try_from(key) {
-                        match input {
-                            Input::Move(kind) => drop(engine.move_cursor(kind)),
-                            Input::HardDrop => {
-                                engine.hard_drop();
-                                lockdown = true;
-                            },
-                            Input::SoftDrop => todo!(),
-                        }
-                        redraw = true;
+                Event::KeyDown { keycode: Some(Keycode::Escape), .. } if !matches!(driver, Driver::Keyboard) => return,
+                Event::KeyDown { keycode: Some(key), repeat, .. } if matches!(driver, Driver::Keyboard) => {
+                    let Ok(input) = Input::try_from(key) else {
+                        continue;
+                    };
+                    match (input, repeat) {
+                        (Input::Move(kind), true) => plays.push(Play::Repeat(kind)),
+                        (Input::SoftDrop, _) => plays.push(Play::SoftDrop),
+                        (_, true) => {},
+                        (Input::Move(kind), false) => plays.push(Play::Move(kind)),
+                        (Input::Rotate(kind), false) => plays.push(Play::Rotate(kind)),
+                        (Input::Hold, false) => plays.push(Play::Hold),
+                        (Input::HardDrop, false) => plays.push(Play::HardDrop),
+                        (Input::Export, false) => println!("{}", fumen::encode(&[fumen::Page::of(&game.engine)])),
+                        (Input::ToggleHint, false) => {
+                            show_hint = !show_hint;
+                            hint.search(&game.engine, show_hint);
+                        },
+                        (Input::Retry, false) => restart = game.retry(),
+                        (Input::Next, false) => restart = game.next(),
                     }
                 },
                 _ => {}
             }
         }
-        if lockdown {
-            engine.line_clear(|_| ());
+
+        match &driver {
+            Driver::Bot(bot) => {
+                if game.is_over() {
+                    // the same engine settings again, as a retry would
+                    if let Some(restarted) = game.retry() {
+                        game = restarted;
+                        start = Instant::now();
+                        plan.clear();
+                    }
+                }
+                if plan.is_empty() {
+                    plan = bot.think(&game.engine).map_or_else(Vec::new, |plan| plan.actions);
+                    plan.reverse();
+                }
+                if last_bot_input.elapsed() >= BOT_INPUT_DELAY {
+                    if let Some(action) = plan.pop() {
+                        plays.push(action.into());
+                        last_bot_input = Instant::now();
+                        last_drop = Instant::now();
+                    }
+                }
+            },
+            Driver::Replay(_) => {
+                while let Some(&(time, play)) = replay.last().filter(|(time, _)| *time <= start.elapsed()) {
+                    game.play(time, play);
+                    replay.pop();
+                }
+            },
+            Driver::Keyboard => {},
         }
 
-        if redraw {
-            draw(&mut canvas, &engine);
+        if !matches!(driver, Driver::Replay(_)) && last_drop.elapsed() >= game.engine.drop_time() {
+            plays.push(Play::Gravity);
+            plan.clear();
         }
 
-        redraw = false;
+        let mut locked = false;
+        for play in plays {
+            if matches!(play, Play::SoftDrop | Play::Gravity) {
+                last_drop = Instant::now();
+            }
+            let clear = game.play(start.elapsed(), play);
+            locked |= clear.is_some();
+            if let Some((server, clear)) = spectators.as_ref().zip(clear) {
+                server.clear(0, &clear);
+            }
+            if play == Play::Hold {
+                hint.search(&game.engine, show_hint);
+            }
+        }
+        if game.should_restart() && matches!(driver, Driver::Keyboard) {
+            restart = game.retry();
+        }
+        if let Some(restarted) = restart {
+            game = restarted;
+            start = Instant::now();
+            last_drop = Instant::now();
+            locked = true;
+        }
+        if locked {
+            hint.search(&game.engine, show_hint);
+        }
+
+        if let Driver::Replay(replay) = &driver {
+            game.tick(start.elapsed().min(replay.time));
+        } else {
+            game.tick(start.elapsed());
+        }
+        if let Some(result) = game.finish() {
+            for line in result {
+                println!("{line}");
+            }
+            if let (Driver::Keyboard, Err(error)) = (&driver, game.replay.save(last_replay_path(&game.replay.mode))) {
+                println!("Fail to save replay: {error}");
+            }
+        }
+
+        let panel = game.panel();
+        let mut overlay = game.mode.overlay(&game.engine);
+        overlay.extend_from_slice(hint.cells());
+        draw(&mut canvas, &[Board { overlay: &overlay, panel: &panel, ..Board::of(&game.engine) }]);
+        if let Some(server) = &mut spectators {
+            server.observe(&[(&game.engine, 0)]);
+        }
     }
 }
 
+fn open_window(sdl: &Sdl, size: Vector2<u32>) -> Canvas<Window> {
+    let video = sdl.video().expect("Fail to acqure display");
+    
+    let window = video
+        .window("Tetris", size.x, size.y)
+        .position_centered()
+        .resizable()
+        .build()
+        .expect("Fail to create window");
+
+    window
+        .into_canvas()
+        .accelerated()
+        .present_vsync()
+        .build()
+        .expect("Fail to render canvas")
+}
+
+
+/// Local versus, every player on one keyboard with the bindings from `VERSUS_KEYS`.
+/// An `opponent` takes the last seat, and the person can use either key set.
+pub fn run_versus(mut versus: Versus, mut opponent: Option<Opponent>, mut spectators: Option<Server>) {
+    let sdl = sdl2::init().expect("Fail to init SDL2");
+    let mut canvas = open_window(&sdl, Vector2::new(INIT_SIZE.x * 2, INIT_SIZE.y));
+    let mut events = sdl.event_pump().expect("Fail to get event loop");
+
+    let mut last_drops = vec![Instant::now(); versus.players.len()];
+    let mut last_frame = Instant::now();
+    let mut last_bot_piece = Instant::now();
+    let computer_seat = opponent.as_ref().map(|_| versus.players.len() - 1);
+    for player in &mut versus.players {
+        let _ = player.engine.spawn();
+    }
+
+    loop {
+        let mut lockdowns = vec![false; versus.players.len()];
+
+        for event in events.poll_iter() {
+            match event {
+                Event::Quit { .. } | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => return,
+                Event::KeyDown { keycode: Some(key), .. } if !versus.is_over() => {
+                    let bound = VERSUS_KEYS.iter().enumerate()
+                        .find_map(|(index, keys)| Some((index, keys.iter().find(|(bound, _)| *bound == key)?.1)))
+                        .map(|(index, input)| (if computer_seat.is_some() { 0 } else { index }, input))
+                        .filter(|(index, _)| *index < versus.players.len());
+                    let Some((index, input)) = bound else {
+                        continue;
+                    };
+
+                    let engine = &mut versus.players[index].engine;
+                    match input {
+                        Input::Move(kind) => drop(engine.move_cursor(kind)),
+                        Input::Rotate(kind) => drop(engine.rotate_cursor(kind)),
+                        Input::Hold => drop(engine.hold()),
+                        Input::HardDrop => {
+                            engine.hard_drop();
+                            lockdowns[index] = true;
+                        },
+                        Input::SoftDrop => {
+                            if engine.soft_drop() {
+                                last_drops[index] = Instant::now();
+                            }
+                        },
+                        Input::ToggleHint | Input::Export | Input::Retry | Input::Next => {},
+                    }
+                },
+                _ => {}
+            }
+        }
+
+        if let Some((opponent, index)) = opponent.as_mut().zip(computer_seat).filter(|_| !versus.is_over()) {
+            let engine = &mut versus.players[index].engine;
+            if last_bot_piece.elapsed() >= opponent.piece_time() && engine.cursor().is_some() {
+                let actions = opponent.plan(engine).map_or_else(Vec::new, |plan| plan.actions);
+                for action in actions {
+                    let _ = action.apply(engine);
+                    lockdowns[index] |= action == Action::HardDrop;
+                }
+                last_bot_piece = Instant::now();
+            }
+        }
+
+        if !versus.is_over() {
+            versus.update(last_frame.elapsed());
+
+            for (index, player) in versus.players.iter_mut().enumerate() {
+                let engine = &mut player.engine;
+                if last_drops[index].elapsed() >= engine.drop_time() && !engine.is_topped_out() {
+                    if engine.cursor_hit_down() {
+                        engine.hard_drop();
+                        lockdowns[index] = true;
+                    } else {
+                        engine.soft_drop();
+                    }
+                    last_drops[index] = Instant::now();
+                }
+            }
+
+            for (index, lockdown) in lockdowns.into_iter().enumerate() {
+                if !lockdown {
+                    continue;
+                }
+                let clear = versus.line_clear(index);
+                if let Some((server, clear)) = spectators.as_ref().zip(clear) {
+                    server.clear(index, &clear);
+                }
+                let engine = &mut versus.players[index].engine;
+                if engine.cursor().is_none() && !engine.is_topped_out() {
+                    let _ = engine.spawn();
+                }
+            }
+
+            if let Some(winner) = versus.winner() {
+                println!("Player {} wins", winner + 1);
+            }
+            if let Some(server) = &mut spectators {
+                let players = versus.players.iter().map(|player| (&player.engine, player.pending())).collect::<Vec<_>>();
+                server.observe(&players);
+                if versus.is_over() {
+                    server.game_over(versus.winner());
+                }
+            }
+        }
+        last_frame = Instant::now();
+
+        let boards = versus.players.iter()
+            .map(|player| Board { pending: player.pending(), ..Board::of(&player.engine) })
+            .collect::<Vec<_>>();
+        draw(&mut canvas, &boards);
+    }
+}
+
+/// Online versus over a connected `session`, the local player may use either key set.
+pub fn run_online(mut session: Box<dyn Session>, mut spectators: Option<Server>) {
+    let sdl = sdl2::init().expect("Fail to init SDL2");
+    let mut canvas = open_window(&sdl, Vector2::new(INIT_SIZE.x * 2, INIT_SIZE.y));
+    let mut events = sdl.event_pump().expect("Fail to get event loop");
+
+    let mut pressed = Inputs::default();
+    let mut last_frame = Instant::now();
+
+    loop {
+        for event in events.poll_iter() {
+            match event {
+                Event::Quit { .. } | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => return,
+                Event::KeyDown { keycode: Some(key), .. } => {
+                    let bound = VERSUS_KEYS.iter().flatten().find(|(bound, _)| *bound == key);
+                    if let Some(&(_, input)) = bound {
+                        pressed.insert(frame_inputs(input));
+                    }
+                },
+                _ => {}
+            }
+        }
+
+        if last_frame.elapsed() >= net::FRAME && !session.simulation().versus.is_over() {
+            match session.poll(pressed) {
+                Ok(true) => {
+                    pressed = Inputs::default();
+                    last_frame = Instant::now();
+                    let versus = &session.simulation().versus;
+                    match versus.winner() {
+                        Some(winner) if winner == session.local() => println!("You win"),
+                        Some(_) => println!("You lose"),
+                        None => {},
+                    }
+                    if let Some(server) = &mut spectators {
+                        let players = versus.players.iter().map(|player| (&player.engine, player.pending())).collect::<Vec<_>>();
+                        server.observe(&players);
+                        if versus.is_over() {
+                            server.game_over(versus.winner());
+                        }
+                    }
+                },
+                Ok(false) => {},
+                Err(error) => {
+                    println!("Online game ended: {error}");
+                    return;
+                },
+            }
+        }
+
+        let boards = session.simulation().versus.players.iter()
+            .map(|player| Board { pending: player.pending(), ..Board::of(&player.engine) })
+            .collect::<Vec<_>>();
+        draw(&mut canvas, &boards);
+    }
+}
+
+fn frame_inputs(input: Input) -> Inputs {
+    match input {
+        Input::Move(MoveKind::Left) => Inputs::LEFT,
+        Input::Move(MoveKind::Right) => Inputs::RIGHT,
+        Input::Rotate(RotateKind::Clockwise) => Inputs::CLOCKWISE,
+        Input::Rotate(RotateKind::CounterClockwise) => Inputs::COUNTER_CLOCKWISE,
+        Input::SoftDrop => Inputs::SOFT_DROP,
+        Input::HardDrop => Inputs::HARD_DROP,
+        Input::Hold => Inputs::HOLD,
+        Input::ToggleHint | Input::Export | Input::Retry | Input::Next => Inputs::default(),
+    }
+}
+
+/// Left player on WASD, right player on the arrows.
+const VERSUS_KEYS: [[(Keycode, Input); 7]; 2] = [
+    [
+        (Keycode::A, Input::Move(MoveKind::Left)),
+        (Keycode::D, Input::Move(MoveKind::Right)),
+        (Keycode::W, Input::HardDrop),
+        (Keycode::S, Input::SoftDrop),
+        (Keycode::E, Input::Rotate(RotateKind::Clockwise)),
+        (Keycode::Q, Input::Rotate(RotateKind::CounterClockwise)),
+        (Keycode::LShift, Input::Hold),
+    ],
+    [
+        (Keycode::Left, Input::Move(MoveKind::Left)),
+        (Keycode::Right, Input::Move(MoveKind::Right)),
+        (Keycode::Up, Input::HardDrop),
+        (Keycode::Down, Input::SoftDrop),
+        (Keycode::Slash, Input::Rotate(RotateKind::Clockwise)),
+        (Keycode::Period, Input::Rotate(RotateKind::CounterClockwise)),
+        (Keycode::RShift, Input::Hold),
+    ],
+];
+
+#[derive(Clone, Copy)]
 enum Input {
     Move(MoveKind),
+    Rotate(RotateKind),
     SoftDrop,
     HardDrop,
+    Hold,
+    ToggleHint,
+    Export,
+    /// Start over, in modes that allow it.
+    Retry,
+    /// Go on to the mode's next stage.
+    Next,
 }
 
 impl TryFrom<Keycode> for Input {
@@ -107,22 +441,174 @@ impl TryFrom<Keycode> for Input {
             Keycode::Left  => Self::Move(MoveKind::Left),
             Keycode::Up    => Self::HardDrop,
             Keycode::Down  => Self::SoftDrop,
+            Keycode::X     => Self::Rotate(RotateKind::Clockwise),
+            Keycode::Z     => Self::Rotate(RotateKind::CounterClockwise),
+            Keycode::C     => Self::Hold,
+            Keycode::P     => Self::ToggleHint,
+            Keycode::F     => Self::Export,
+            Keycode::R     => Self::Retry,
+            Keycode::N     => Self::Next,
             _ => return Err(())
         })
     }
 }
 
 
-fn draw(canvas: &mut Canvas<Window>, engine: &Engine) {
+/// A position for the hint search: the matrix, the piece in hand, hold and queue.
+type HintPosition = (Matrix, PieceKind, Option<PieceKind>, Vec<PieceKind>);
+type HintCells = Vec<(Point2<usize>, SemanticColor)>;
+
+/// Where the perfect clear solver would put the current piece. The search
+/// runs on a thread of its own so the render loop never waits on it,
+/// answers for positions already left behind are dropped.
+struct Hint {
+    requests: Sender<(u64, HintPosition)>,
+    answers: Receiver<(u64, HintCells)>,
+    /// Latest position searched, counting up.
+    asked: u64,
+    cells: HintCells,
+}
+
+impl Hint {
+    fn new() -> Self {
+        let (requests, positions) = mpsc::channel::<(u64, HintPosition)>();
+        let (found, answers) = mpsc::channel();
+        thread::spawn(move || {
+            while let Ok(mut request) = positions.recv() {
+                // only the latest position is still worth a search
+                request = positions.try_iter().last().unwrap_or(request);
+                let (id, (matrix, current, hold, queue)) = request;
+                let cells = perfect_clear::solve(&matrix, current, hold, &queue, PERFECT_CLEAR_HEIGHT, PERFECT_CLEAR_BUDGET)
+                    .and_then(|steps| steps.first().copied())
+                    .map(|step| step.piece.cells().unwrap().into_iter().map(|coord| (coord, step.piece.kind.color())).collect())
+                    .unwrap_or_default();
+                if found.send((id, cells)).is_err() {
+                    return;
+                }
+            }
+        });
+        Hint { requests, answers, asked: 0, cells: Vec::new() }
+    }
+
+    /// Hides the hint and starts looking for the one on `engine`, if `enabled`.
+    fn search(&mut self, engine: &Engine, enabled: bool) {
+        self.asked += 1;
+        self.cells.clear();
+        if let Some(cursor) = engine.cursor().filter(|_| enabled) {
+            let position = (*engine.matrix(), cursor.kind, engine.held(), engine.queue().collect());
+            let _ = self.requests.send((self.asked, position));
+        }
+    }
+
+    /// The hint for the latest position, empty until the search finds one.
+    fn cells(&mut self) -> &[(Point2<usize>, SemanticColor)] {
+        for (id, cells) in self.answers.try_iter() {
+            if id == self.asked {
+                self.cells = cells;
+            }
+        }
+        &self.cells
+    }
+}
+
+/// Read-only window following a game streamed by `spectate::Server` at `address`.
+pub fn run_spectator(address: &str) {
+    let stream = std::net::TcpStream::connect(address).expect("Fail to connect to the game");
+    let mut connection = net::Connection::new(stream).expect("Fail to read the game");
+
+    let sdl = sdl2::init().expect("Fail to init SDL2");
+    let mut canvas = open_window(&sdl, INIT_SIZE);
+    let mut events = sdl.event_pump().expect("Fail to get event loop");
+    let mut views: Vec<View> = Vec::new();
+
+    loop {
+        for event in events.poll_iter() {
+            if let Event::Quit { .. } | Event::KeyDown { keycode: Some(Keycode::Escape), .. } = event {
+                return;
+            }
+        }
+
+        loop {
+            let message = match connection.try_receive() {
+                Ok(Some(message)) => message,
+                Ok(None) => break,
+                Err(error) => {
+                    println!("Stream ended: {error}");
+                    return;
+                },
+            };
+            match spectate::apply(&mut views, &message) {
+                Ok(Some(Some(winner))) => println!("Player {} wins", winner + 1),
+                Ok(_) => {},
+                Err(error) => println!("Skipping {error}"),
+            }
+        }
+
+        let boards = views.iter()
+            .map(|view| Board {
+                matrix: &view.matrix,
+                cursor: view.cursor,
+                overlay: &[],
+                pending: view.pending,
+                panel: &[],
+                stack_alpha: None,
+            })
+            .collect::<Vec<_>>();
+        draw(&mut canvas, &boards);
+    }
+}
+
+/// What one board on screen shows.
+struct Board<'game> {
+    matrix: &'game Matrix,
+    cursor: Option<Piece>,
+    /// Cells drawn translucent over the matrix, for hints and targets.
+    overlay: &'game [(Point2<usize>, SemanticColor)],
+    /// Incoming garbage lines, shown as a meter left of the matrix.
+    pending: usize,
+    /// Text for the score area.
+    panel: &'game [String],
+    /// Opacity of every matrix cell in `cells` order, all opaque when `None`.
+    stack_alpha: Option<Vec<f32>>,
+}
+
+impl<'game> Board<'game> {
+    fn of(engine: &'game Engine) -> Self {
+        Board {
+            matrix: engine.matrix(),
+            cursor: engine.cursor(),
+            overlay: &[],
+            pending: 0,
+            panel: &[],
+            stack_alpha: (engine.visibility() != Visibility::Visible)
+                .then(|| engine.cells().map(|(_, _, locked_at)| engine.cell_alpha(locked_at)).collect()),
+        }
+    }
+}
+
+/// Lays the boards out side by side, each in its own square.
+fn draw(canvas: &mut Canvas<Window>, boards: &[Board]) {
     canvas.set_draw_color(BACKGROUND_COLOR);
     canvas.clear();
 
+    let viewport = canvas.viewport();
+    let column_width = viewport.width() / boards.len().max(1) as u32;
+
+    for (index, board) in boards.iter().enumerate() {
+        let column = Rect::new((column_width * index as u32) as i32, 0, column_width, viewport.height());
+        draw_board(canvas, column, board);
+    }
+
+    canvas.present();
+}
+
+fn draw_board(canvas: &mut Canvas<Window>, column: Rect, board: &Board) {
     let ui_sqare = {
-            let Vector2{x,y} = Vector2::from(canvas.viewport().size())
+            let Vector2{x,y} = Vector2::from(column.size())
                 .cast::<i32>()
                 .unwrap();
         
-            if x > y {
+            let mut square = if x > y {
         
                 let mid_point = x / 2;
                 let left_edge = mid_point - (y / 2);
@@ -132,12 +618,12 @@ fn draw(canvas: &mut Canvas<Window>, engine: &Engine) {
                 let mid_point = y / 2;
                 let top_edge =  mid_point - (x / 2);
                 Rect::new(0,top_edge,x as u32,x as u32)
-            }
-
-
+            };
+            square.offset(column.x(), column.y());
+            square
     };
 
-    let matrix = {
+    let (matrix, garbage_meter) = {
 
         let mut middle_section = ui_sqare;  // section with game field
         middle_section.set_width(middle_section.width() / 2);
@@ -149,7 +635,17 @@ fn draw(canvas: &mut Canvas<Window>, engine: &Engine) {
         matrix.set_height((matrix.height() as f32 * (7.0 / 8.0)) as _);
 
         matrix.center_on(middle_section.center());
-        matrix
+
+        let margin = (matrix.left() - middle_section.left()) as u32;
+        let lines = board.pending.min(Matrix::HEIGHT) as u32;
+        let meter_height = matrix.height() * lines / Matrix::HEIGHT as u32;
+        let meter = Rect::new(
+            middle_section.left() + (margin / 4) as i32,
+            matrix.bottom() - meter_height as i32,
+            margin / 2,
+            meter_height,
+        );
+        (matrix, meter)
     };
 
     let up_next = {
@@ -222,6 +718,13 @@ fn draw(canvas: &mut Canvas<Window>, engine: &Engine) {
         canvas.fill_rect(*sub_rect).unwrap();
     }
 
+    text::draw_lines(canvas, score_area, board.panel, PANEL_COLUMNS, Color::WHITE);
+
+    if board.pending > 0 {
+        canvas.set_draw_color(SemanticColor::Red.screen_color());
+        canvas.fill_rect(garbage_meter).unwrap();
+    }
+
     let mut cell_draw_ctx = CellDrawContext {
         origin: matrix.bottom_left(),
         dims: Vector2::from(matrix.size()),
@@ -229,21 +732,25 @@ fn draw(canvas: &mut Canvas<Window>, engine: &Engine) {
     };
     
     
-    for (coord, cell) in engine.cells() {
-        cell_draw_ctx.try_draw_cell(coord, cell);
+    for (i, (coord, cell, _)) in board.matrix.cells().enumerate() {
+        let alpha = board.stack_alpha.as_ref().map_or(1.0, |alpha| alpha[i]);
+        match cell {
+            Some(color) if alpha >= 1.0 => cell_draw_ctx.draw_cell(coord, color),
+            Some(color) if alpha > 0.0 => cell_draw_ctx.draw_translucent_cell(coord, color, (alpha * 255.0) as u8),
+            _ => {},
+        }
+    }
+
+    for &(coord, color) in board.overlay {
+        cell_draw_ctx.draw_overlay_cell(coord, color);
     }
 
-    if let Some ((cursor_cells , cursor_color)) = engine.cursor_info() {
+    if let Some ((cursor_cells , cursor_color)) = board.cursor.and_then(|cursor| Some((cursor.squares()?, cursor.kind.color()))) {
         for coord in cursor_cells  {
             cell_draw_ctx.draw_cell(coord, cursor_color);  
         }
 
     }
-    
-
-
-
-    canvas.present();
 }
 
 struct CellDrawContext<'canvas> {
@@ -255,36 +762,41 @@ struct CellDrawContext<'canvas> {
 impl CellDrawContext<'_> {
     const CELL_COUNT: Vector2<u32> = Vector2::new(Matrix::WIDTH as u32, Matrix::HEIGHT as u32);
     
-    fn try_draw_cell(
-        &mut self, 
-        coord: Point2<usize>, 
-        cell: Option<SemanticColor>, 
-    ) {
-        let Some(color) = cell else {
-            return;
-        };
-        self.draw_cell(coord, color);
-    }
-
-
     fn draw_cell( 
             &mut self, 
             coord: Point2<usize>, 
             color: SemanticColor, 
         ) {
+        let cell_rect = self.cell_rect(coord);
+        self.canvas.set_draw_color(color.screen_color());
+        self.canvas.fill_rect(cell_rect).unwrap();
+    }
+
+    fn draw_overlay_cell(&mut self, coord: Point2<usize>, color: SemanticColor) {
+        self.draw_translucent_cell(coord, color, OVERLAY_ALPHA);
+    }
+
+    fn draw_translucent_cell(&mut self, coord: Point2<usize>, color: SemanticColor, alpha: u8) {
+        let cell_rect = self.cell_rect(coord);
+        let Color { r, g, b, .. } = color.screen_color();
+
+        self.canvas.set_blend_mode(BlendMode::Blend);
+        self.canvas.set_draw_color(Color::RGBA(r, g, b, alpha));
+        self.canvas.fill_rect(cell_rect).unwrap();
+        self.canvas.set_blend_mode(BlendMode::None);
+    }
+
+    fn cell_rect(&self, coord: Point2<usize>) -> Rect {
         let coord = coord.to_vec().cast::<u32>().unwrap();
         let this = (coord + Vector2::new(0,1)).mul_element_wise(self.dims).div_element_wise(Self::CELL_COUNT);
         let next = (coord + Vector2::new(1,0)).mul_element_wise(self.dims).div_element_wise(Self::CELL_COUNT);
 
-        let cell_rect = Rect::new(
+        Rect::new(
             self.origin.x + this.x as i32,
             self.origin.y - this.y as i32,
             next.x - this.x,
             this.y - next.y,
-        );
-
-        self.canvas.set_draw_color(color.screen_color());
-        self.canvas.fill_rect(cell_rect).unwrap();
+        )
     }
     
 }
\ No newline at end of file
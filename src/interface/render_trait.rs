@@ -15,6 +15,8 @@ impl ScreenColor for SemanticColor{
             SemanticColor::Blue   => SdlColor::RGB(0x72,0x9f,0xcf),
             SemanticColor::Green  => SdlColor::RGB(0x4e,0x9a,0x06),
             SemanticColor::Red    => SdlColor::RGB(0xcc,0x00,0x00),
+            SemanticColor::Garbage => SdlColor::RGB(0x55,0x57,0x53),
+            SemanticColor::Rgb(r, g, b) => SdlColor::RGB(*r, *g, *b),
         }
     }
 
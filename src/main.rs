@@ -1,24 +1,271 @@
 #![allow(dead_code)]
 #![feature(array_chunks, slice_pattern)]
 
-use engine::{Engine, Matrix, Color, piece::Kind as PieceKind};
+use engine::{Engine, Matrix};
+use interface::Driver;
+use mode::Game;
 
 
+mod bot;
 mod engine;
+mod fumen;
 mod interface;
+mod json;
+mod mode;
+mod net;
+mod spectate;
+mod tbp;
+mod versus;
 
 fn main() {
     let mut matrix = Matrix::blank();
-    /*for col in 0..=5{
-        matrix[(col,0).into()] = Some(Color::Green);
+
+    let args = std::env::args().collect::<Vec<_>>();
+    if let Some(path) = option_value(&args, "--board") {
+        let text = std::fs::read_to_string(path).unwrap_or_else(|error| panic!("Fail to read {path}: {error}"));
+        matrix = text.parse().unwrap_or_else(|error| panic!("Fail to parse {path}: {error}"));
+    }
+    if let Some(code) = option_value(&args, "--fumen") {
+        let pages = fumen::decode(code).unwrap_or_else(|error| panic!("Fail to read fumen: {error}"));
+        matrix = pages.first().map_or(matrix, |page| page.matrix);
+    }
+
+    let seed = rand::random();
+    let mut engine = engine_from_args(&args, seed);
+    engine.set_matrix(matrix);
+    let spectators = spectators_from_args(&args);
+
+    match args.get(1).map(String::as_str) {
+        Some("demo") => interface::run(free_play(engine, seed), Driver::Bot(bot_from_args(&args[2..])), spectators),
+        Some("sprint") => {
+            let (game, driver) = mode_from_args(&args[2..], mode::Sprint::record_path(), |_| Box::new(mode::Sprint::new()));
+            interface::run(game, driver, spectators)
+        },
+        Some("ultra") => {
+            let (game, driver) = mode_from_args(&args[2..], mode::last_replay_path("ultra"), |args| Box::new(ultra_from_args(args)));
+            interface::run(game, driver, spectators)
+        },
+        Some("marathon") => {
+            let (game, driver) = mode_from_args(&args[2..], mode::last_replay_path("marathon"), |args| Box::new(marathon_from_args(args)));
+            interface::run(game, driver, spectators)
+        },
+        Some("dig") => {
+            let (game, driver) = mode_from_args(&args[2..], mode::last_replay_path("dig"), |args| Box::new(dig_from_args(args)));
+            interface::run(game, driver, spectators)
+        },
+        Some("master") => {
+            let (game, driver) = mode_from_args(&args[2..], mode::last_replay_path("master"), |_| Box::new(mode::Master::new()));
+            interface::run(game, driver, spectators)
+        },
+        Some("puzzle") => {
+            let (game, driver) = mode_from_args(&args[2..], mode::last_replay_path("puzzle"), |args| Box::new(puzzles_from_args(args)));
+            interface::run(game, driver, spectators)
+        },
+        Some("practice") => {
+            let (game, driver) = mode_from_args(&args[2..], mode::last_replay_path("practice"), |args| Box::new(practice_from_args(args)));
+            interface::run(game, driver, spectators)
+        },
+        Some("tbp-bot") => tbp::host::run(bot_from_args(&args[2..])).expect("TBP connection failed"),
+        Some("tune") => bot::tune::run(&args[2..]),
+        Some("host") => interface::run_online(host_from_args(&args[2..]), spectators),
+        Some("join") => interface::run_online(join_from_args(&args[2..]), spectators),
+        Some("versus") => interface::run_versus(versus_from_args(&args[2..]), opponent_from_args(&args[2..]), spectators),
+        Some("spectate") => {
+            let default = format!("127.0.0.1:{}", spectate::DEFAULT_PORT);
+            interface::run_spectator(args.get(2).unwrap_or(&default))
+        },
+        _ => {
+            let mut game = free_play(engine, seed);
+            game.restart_on_fault = args.iter().any(|arg| arg == "--finesse-restart");
+            game.replay.args = args[1..].to_vec();
+            interface::run(game, Driver::Keyboard, spectators)
+        },
+    }
+}
+
+/// `seed` is what `engine` was made with.
+fn free_play(engine: Engine, seed: u64) -> Game {
+    Game::new(engine, seed, Box::new(mode::Free))
+}
+
+/// A fresh game of `mode`, starting over on every finesse fault with
+/// `--finesse-restart`, or with `--replay [FILE]` the playback of `FILE`
+/// (`record` by default) under the options it was recorded with.
+fn mode_from_args(args: &[String], record: std::path::PathBuf, mode: impl Fn(&[String]) -> Box<dyn mode::Mode>) -> (Game, Driver) {
+    let Some(index) = args.iter().position(|arg| arg == "--replay") else {
+        let seed = rand::random();
+        let mut game = Game::new(engine_from_args(args, seed), seed, mode(args));
+        game.restart_on_fault = args.iter().any(|arg| arg == "--finesse-restart");
+        game.replay.args = args.to_vec();
+        return (game, Driver::Keyboard);
+    };
+    let path = args.get(index + 1).map_or(record, std::path::PathBuf::from);
+    let replay = mode::replay::Replay::load(&path).unwrap_or_else(|error| panic!("Fail to load {}: {error}", path.display()));
+    let game = Game::new(engine_from_args(&replay.args, replay.seed), replay.seed, mode(&replay.args));
+    if game.replay.mode != replay.mode {
+        panic!("Fail to replay {}: it is a {} game, not {}", path.display(), replay.mode, game.replay.mode);
+    }
+    (game, Driver::Replay(replay))
+}
+
+fn engine_from_args(args: &[String], seed: u64) -> Engine {
+    let mut engine = Engine::with_seed(seed);
+    engine.set_visibility(visibility_from_args(args));
+    engine.set_clear_gravity(clear_gravity_from_args(args));
+    engine.set_big(args.iter().any(|arg| arg == "--big"));
+    if let Some(piece_set) = piece_set_from_args(args) {
+        engine.set_piece_set(piece_set);
+    }
+    engine
+}
+
+/// Pieces to deal, `--piece-set` naming a bundled set or a set file.
+fn piece_set_from_args(args: &[String]) -> Option<engine::PieceSet> {
+    let name = option_value(args, "--piece-set")?;
+    let piece_set = engine::PieceSet::named(name)
+        .map_or_else(|| engine::PieceSet::load(name), Ok)
+        .unwrap_or_else(|error| panic!("Fail to load --piece-set {name}: {error}"));
+    Some(piece_set)
+}
+
+/// How the stack falls into cleared lines, `--clear-gravity naive|sticky|cascade`.
+fn clear_gravity_from_args(args: &[String]) -> engine::ClearGravity {
+    option_value(args, "--clear-gravity")
+        .map_or_else(Default::default, |gravity| gravity.parse().unwrap_or_else(|error| panic!("{error}")))
+}
+
+/// Locked cells disappear with `--invisible`, or fade out over `--fade SECONDS`.
+fn visibility_from_args(args: &[String]) -> engine::Visibility {
+    if args.iter().any(|arg| arg == "--invisible") {
+        return engine::Visibility::Invisible;
+    }
+    option_value(args, "--fade").map_or(engine::Visibility::Visible, |seconds| {
+        engine::Visibility::Fading(std::time::Duration::from_secs_f64(seconds.parse().expect("Fail to parse --fade")))
+    })
+}
+
+/// Timed score attack lasting `--minutes M` (default 2), with
+/// `--combo-multiplier` scaling clears by the combo.
+fn ultra_from_args(args: &[String]) -> mode::Ultra {
+    let duration = option_value(args, "--minutes").map_or(mode::Ultra::DURATION, |minutes| {
+        std::time::Duration::from_secs_f64(minutes.parse::<f64>().expect("Fail to parse --minutes") * 60.0)
+    });
+    mode::Ultra::new(duration, args.iter().any(|arg| arg == "--combo-multiplier"))
+}
+
+/// Marathon from `--level N` (1 to 15), past the goal with `--endless`,
+/// with NES style delays under `--classic`.
+fn marathon_from_args(args: &[String]) -> mode::Marathon {
+    let level = option_value(args, "--level").map_or(1, |level| level.parse().expect("Fail to parse --level"));
+    let mut marathon = mode::Marathon::new(level, args.iter().any(|arg| arg == "--endless"));
+    if args.iter().any(|arg| arg == "--classic") {
+        marathon.timing = engine::Timing::CLASSIC;
     }
-    for col in 0..=2{
-        matrix[(col,1).into()] = Some(Color::Yellow);
-    }*/
+    marathon
+}
+
+/// Dig through `--rows N` of garbage with `--messiness F` chance of the
+/// hole moving, getting a new row every `--survival SECONDS` if given.
+fn dig_from_args(args: &[String]) -> mode::Dig {
+    let rows = option_value(args, "--rows").map_or(mode::Dig::ROWS, |rows| rows.parse().expect("Fail to parse --rows"));
+    let messiness = option_value(args, "--messiness")
+        .map_or(mode::Dig::MESSINESS, |messiness| messiness.parse().expect("Fail to parse --messiness"));
+    let survival = option_value(args, "--survival")
+        .map(|seconds| std::time::Duration::from_secs_f64(seconds.parse().expect("Fail to parse --survival")));
+    mode::Dig::new(rows, messiness, survival)
+}
+
+/// Puzzles from `--puzzles PATH`, a puzzle file or a directory of them,
+/// else the bundled pack followed by the user's. Starts at `--number N`.
+fn puzzles_from_args(args: &[String]) -> mode::Puzzles {
+    let pack = match option_value(args, "--puzzles").map(std::path::Path::new) {
+        Some(path) if path.is_dir() => mode::Puzzle::load_dir(path),
+        Some(path) => mode::Puzzle::load(path).map(|puzzle| vec![puzzle]),
+        None => {
+            let user_dir = mode::Puzzle::user_dir();
+            let user = match user_dir.is_dir() {
+                true => mode::Puzzle::load_dir(user_dir),
+                false => Ok(Vec::new()),
+            };
+            user.map(|user| mode::Puzzle::bundled().into_iter().chain(user).collect())
+        },
+    };
+    let pack = pack.unwrap_or_else(|error| panic!("Fail to load puzzles: {error}"));
+    let number: usize = option_value(args, "--number").map_or(1, |number| number.parse().expect("Fail to parse --number"));
+    mode::Puzzles::new(pack, number.saturating_sub(1))
+}
+
+/// Drills the setup in the file given by `--setup FILE`.
+fn practice_from_args(args: &[String]) -> mode::Practice {
+    let path = option_value(args, "--setup").expect("Fail to find --setup FILE");
+    let setup = mode::Setup::load(path).unwrap_or_else(|error| panic!("Fail to load {path}: {error}"));
+    mode::Practice::new(setup)
+}
 
-    let mut engine = Engine::with_matrix(matrix);
+/// Streams the game to `tetris spectate` clients on local port `--spectate PORT`.
+fn spectators_from_args(args: &[String]) -> Option<spectate::Server> {
+    let port: u16 = option_value(args, "--spectate")?.parse().expect("Fail to parse --spectate");
+    Some(spectate::Server::bind(("127.0.0.1", port)).expect("Fail to start spectator server"))
+}
 
-    engine.db_test_cursor(PieceKind::I, (5,5).into());
-    
-    interface::run(engine)
+/// Default bot, or one using `--weights FILE` as written by `tetris tune`.
+fn bot_from_args(args: &[String]) -> bot::Bot {
+    let Some(path) = option_value(args, "--weights") else {
+        return bot::Bot::default();
+    };
+    let weights = bot::Weights::load(path).unwrap_or_else(|error| panic!("Fail to load {path}: {error}"));
+    bot::Bot { weights, ..bot::Bot::default() }
 }
+
+/// Two player game, `--seed N` for the piece sequence and `--attack FILE` for the attack table.
+fn versus_from_args(args: &[String]) -> versus::Versus {
+    let seed = option_value(args, "--seed").map_or_else(rand::random, |seed| seed.parse().expect("Fail to parse --seed"));
+    let mut settings = versus::Settings::default();
+    if let Some(path) = option_value(args, "--attack") {
+        settings.attack = versus::AttackTable::load(path).unwrap_or_else(|error| panic!("Fail to load {path}: {error}"));
+    }
+    versus::Versus::new(2, seed, settings)
+}
+
+/// Computer opponent for `--cpu easy|medium|hard`, fine tuned by
+/// `--pps`, `--depth` and `--mistakes`, playing with `--weights FILE`.
+fn opponent_from_args(args: &[String]) -> Option<bot::Opponent> {
+    let mut difficulty: bot::Difficulty = option_value(args, "--cpu")?
+        .parse()
+        .unwrap_or_else(|error| panic!("{error}"));
+    if let Some(pps) = option_value(args, "--pps") {
+        difficulty.pps = pps.parse().expect("Fail to parse --pps");
+    }
+    if let Some(depth) = option_value(args, "--depth") {
+        difficulty.lookahead = depth.parse().expect("Fail to parse --depth");
+    }
+    if let Some(rate) = option_value(args, "--mistakes") {
+        difficulty.mistake_rate = rate.parse().expect("Fail to parse --mistakes");
+    }
+    Some(bot::Opponent::new(bot_from_args(args).weights, difficulty))
+}
+
+/// Waits on `--port P` (default 7878) for one player. Plays lockstep with
+/// `--delay FRAMES` of input delay, or rollback with `--rollback`.
+fn host_from_args(args: &[String]) -> Box<dyn net::Session> {
+    let port: u16 = option_value(args, "--port").map_or(7878, |port| port.parse().expect("Fail to parse --port"));
+    let delay = option_value(args, "--delay")
+        .map_or(net::lockstep::DEFAULT_DELAY, |delay| delay.parse().expect("Fail to parse --delay"));
+    let mode = match args.iter().any(|arg| arg == "--rollback") {
+        true => net::Mode::Rollback,
+        false => net::Mode::Lockstep(delay),
+    };
+    let listener = std::net::TcpListener::bind(("0.0.0.0", port)).expect("Fail to listen");
+    println!("Waiting for a player on port {port}");
+    net::host(&listener, rand::random(), mode).expect("Fail to start online game")
+}
+
+/// Joins the game hosted at `ADDRESS:PORT`.
+fn join_from_args(args: &[String]) -> Box<dyn net::Session> {
+    let address = args.first().expect("Usage: tetris join ADDRESS:PORT");
+    net::join(address.as_str()).expect("Fail to join online game")
+}
+
+fn option_value<'args>(args: &'args [String], flag: &str) -> Option<&'args String> {
+    args.iter().skip_while(|arg| *arg != flag).nth(1)
+}
\ No newline at end of file
@@ -0,0 +1,123 @@
+use std::time::Duration;
+use crate::engine::{Engine, LineClear};
+use super::{Mode, Stats, format_time, replay::Replay};
+
+/// Dig through a stack of garbage as fast as possible, or keep digging
+/// as new rows come in for survival dig.
+pub struct Dig {
+    pub rows: usize,
+    /// Chance for each row to move the hole, like versus garbage.
+    pub messiness: f64,
+    /// Time between new garbage rows in survival dig.
+    pub survival: Option<Duration>,
+    /// Garbage rows in the matrix after the last lock or new row.
+    left: usize,
+    dug: usize,
+    next_row: Duration,
+}
+
+impl Dig {
+    pub const ROWS: usize = 10;
+    pub const MESSINESS: f64 = 0.5;
+
+    pub fn new(rows: usize, messiness: f64, survival: Option<Duration>) -> Self {
+        Dig {
+            rows,
+            messiness,
+            survival,
+            left: 0,
+            dug: 0,
+            next_row: survival.unwrap_or_default(),
+        }
+    }
+}
+
+impl Default for Dig {
+    fn default() -> Self {
+        Self::new(Self::ROWS, Self::MESSINESS, None)
+    }
+}
+
+impl Mode for Dig {
+    fn name(&self) -> &'static str {
+        "dig"
+    }
+
+    fn start(&mut self, engine: &mut Engine) {
+        let hole = engine.random_column();
+        let _ = engine.add_garbage(self.rows, hole, self.messiness);
+        self.left = engine.matrix().garbage_rows();
+    }
+
+    fn tick(&mut self, engine: &mut Engine, stats: &Stats) {
+        let Some(interval) = self.survival else {
+            return;
+        };
+        while stats.time >= self.next_row && !engine.is_topped_out() {
+            let hole = engine.random_column();
+            let _ = engine.add_garbage(1, hole, self.messiness);
+            self.left = engine.matrix().garbage_rows();
+            self.next_row += interval;
+        }
+    }
+
+    fn locked(&mut self, engine: &mut Engine, _stats: &Stats, _clear: &LineClear) {
+        let left = engine.matrix().garbage_rows();
+        self.dug += self.left.saturating_sub(left);
+        self.left = left;
+    }
+
+    fn is_over(&self, _stats: &Stats) -> bool {
+        self.survival.is_none() && self.left == 0
+    }
+
+    fn panel(&self, stats: &Stats) -> Vec<String> {
+        let garbage = match self.survival {
+            Some(_) => format!("GARBAGE {}", self.left),
+            None => format!("LEFT {}/{}", self.left, self.rows),
+        };
+        vec![
+            format!("TIME {}", format_time(stats.time)),
+            garbage,
+            format!("DUG {}", self.dug),
+            format!("PIECES {}", stats.pieces),
+        ]
+    }
+
+    fn finish(&mut self, stats: &Stats, _replay: &Replay) -> Vec<String> {
+        let summary = match (self.survival, self.left) {
+            (Some(_), _) => format!("Dug {} rows, survived {}", self.dug, format_time(stats.time)),
+            (None, 0) => format!("Dug {} rows in {}", self.rows, format_time(stats.time)),
+            (None, left) => format!("Topped out with {left} of {} rows left", self.rows),
+        };
+        vec![summary, format!("{} pieces, {:.2} per second", stats.pieces, stats.pps())]
+    }
+
+    fn retry(&self) -> Option<Box<dyn Mode>> {
+        Some(Box::new(Dig::new(self.rows, self.messiness, self.survival)))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mode::test::{bot_play, seeded};
+
+    #[test]
+    fn dig_race_and_survival() {
+        let mut game = seeded(3, Box::new(Dig::new(4, 1.0, None)));
+        assert_eq!(game.engine.matrix().garbage_rows(), 4);
+        bot_play(&mut game, 100, Duration::from_millis(50));
+        assert!(game.is_over() && !game.engine.is_topped_out());
+        assert_eq!(game.engine.matrix().garbage_rows(), 0);
+        assert!(game.finish().unwrap()[0].starts_with("Dug 4 rows"));
+
+        let survival = Dig::new(2, 0.0, Some(Duration::from_secs(1)));
+        let mut game = seeded(3, Box::new(survival));
+        game.tick(Duration::from_millis(2500));
+        assert_eq!(game.engine.matrix().garbage_rows(), 4);
+        bot_play(&mut game, 10, Duration::from_millis(50));
+        assert!(!game.is_over());
+    }
+}
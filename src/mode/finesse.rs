@@ -0,0 +1,138 @@
+use std::collections::{HashSet, VecDeque};
+use crate::engine::{Coordinate, Matrix, MoveKind, Offset, notation, piece::{Piece, Kind as PieceKind, RotateKind}};
+
+/// Fewest inputs that put `start` where `target` lands, pressing moves,
+/// rotations and held moves (which slide to the wall) before a hard drop.
+/// `None` when the target needs soft drops, like tucks and spins.
+pub fn minimal_inputs(matrix: &Matrix, start: Piece, target: &Piece) -> Option<usize> {
+    let goal = landed_cells(matrix, *target)?;
+    let mut seen = HashSet::from([start]);
+    let mut queue = VecDeque::from([(start, 0)]);
+
+    while let Some((piece, inputs)) = queue.pop_front() {
+        if landed_cells(matrix, piece).as_ref() == Some(&goal) {
+            return Some(inputs);
+        }
+        for next in successors(matrix, piece) {
+            if seen.insert(next) {
+                queue.push_back((next, inputs + 1));
+            }
+        }
+    }
+    None
+}
+
+pub fn dropped(matrix: &Matrix, mut piece: Piece) -> Piece {
+    let down = Offset::new(0, -1);
+    while !matrix.is_clipping(&piece.moved_by(down)) {
+        piece = piece.moved_by(down);
+    }
+    piece
+}
+
+fn landed_cells(matrix: &Matrix, piece: Piece) -> Option<Vec<Coordinate>> {
+    let mut cells = dropped(matrix, piece).cells()?;
+    cells.sort_by_key(|coord| (coord.y, coord.x));
+    Some(cells)
+}
+
+fn successors(matrix: &Matrix, piece: Piece) -> impl Iterator<Item = Piece> + '_ {
+    let shift = move |kind: MoveKind| {
+        let moved = piece.stepped(kind.offset());
+        (!matrix.is_clipping(&moved)).then_some(moved)
+    };
+    let slide = move |kind: MoveKind| {
+        let mut slid = shift(kind)?;
+        while !matrix.is_clipping(&slid.stepped(kind.offset())) {
+            slid = slid.stepped(kind.offset());
+        }
+        Some(slid)
+    };
+    let turn = move |kind| matrix.rotated(&piece, kind);
+
+    [
+        shift(MoveKind::Left),
+        shift(MoveKind::Right),
+        slide(MoveKind::Left),
+        slide(MoveKind::Right),
+        turn(RotateKind::Clockwise),
+        turn(RotateKind::CounterClockwise),
+    ].into_iter().flatten()
+}
+
+
+/// Pieces of one kind judged for finesse, and the faults among them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tally {
+    pub kind: PieceKind,
+    pub pieces: usize,
+    pub faults: usize,
+}
+
+/// Counts a judged piece of `kind` in `tallies`, kept in the order kinds first show up.
+pub fn record(tallies: &mut Vec<Tally>, kind: PieceKind, fault: bool) {
+    let index = match tallies.iter().position(|tally| tally.kind == kind) {
+        Some(index) => index,
+        None => {
+            tallies.push(Tally { kind, pieces: 0, faults: 0 });
+            tallies.len() - 1
+        },
+    };
+    tallies[index].pieces += 1;
+    tallies[index].faults += fault as usize;
+}
+
+/// `T 1/12, L 0/8`, faults over judged pieces per kind.
+pub fn breakdown(tallies: &[Tally]) -> String {
+    tallies.iter()
+        .map(|tally| format!("{} {}/{}", notation::letter(tally.kind), tally.faults, tally.pieces))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::engine::{Engine, piece::Rotation};
+    use crate::mode::{Free, Game, replay::Play};
+
+    #[test]
+    fn finesse_counts() {
+        let matrix = Matrix::blank();
+        let spawned = |kind| Piece::spawned(kind);
+        let inputs = |kind, rotation, x| {
+            let target = Piece { kind, rotation, position: Offset::new(x, 0), big: false };
+            minimal_inputs(&matrix, spawned(kind), &target)
+        };
+
+        assert_eq!(inputs(PieceKind::O, Rotation::N, 3), Some(0));
+        assert_eq!(inputs(PieceKind::O, Rotation::N, -1), Some(1));
+        assert_eq!(inputs(PieceKind::T, Rotation::N, 1), Some(2));
+        assert_eq!(inputs(PieceKind::T, Rotation::E, 7), Some(2));
+        assert_eq!(inputs(PieceKind::I, Rotation::E, -2), Some(2));
+    }
+
+    #[test]
+    fn faults_by_kind() {
+        let mut game = Game::new(Engine::with_seed(5), 5, Box::new(Free));
+        game.restart_on_fault = true;
+        let kind = game.engine.cursor().unwrap().kind;
+        for play in [Play::Move(MoveKind::Left), Play::Move(MoveKind::Right), Play::HardDrop] {
+            game.play(std::time::Duration::ZERO, play);
+        }
+        assert_eq!(game.stats.finesse, [Tally { kind, pieces: 1, faults: 1 }]);
+        assert_eq!(game.panel().last().unwrap(), "FAULTS 1");
+        assert!(game.should_restart());
+
+        let retried = game.retry().unwrap();
+        assert!(retried.restart_on_fault && !retried.should_restart());
+        assert_eq!(retried.engine.cursor().unwrap().kind, kind);
+
+        let mut tallies = Vec::new();
+        for (kind, fault) in [(PieceKind::T, true), (PieceKind::O, false), (PieceKind::T, false)] {
+            record(&mut tallies, kind, fault);
+        }
+        assert_eq!(breakdown(&tallies), "T 1/2, O 0/1");
+    }
+}
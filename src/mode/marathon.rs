@@ -0,0 +1,107 @@
+use crate::engine::{Engine, LineClear, Timing};
+use super::{Mode, Stats, format_time, replay::Replay, score::Score};
+
+/// Clear 150 lines while gravity speeds up every 10, or keep going
+/// for as long as possible when endless.
+pub struct Marathon {
+    pub start_level: u8,
+    pub goal: usize,
+    pub endless: bool,
+    /// Delays to play under, `Timing::CLASSIC` for NES style.
+    pub timing: Timing,
+    score: Score,
+    /// Level of the piece being played, the engine may already be past it.
+    level: u8,
+}
+
+impl Marathon {
+    pub const GOAL: usize = 150;
+    pub const MAX_START_LEVEL: u8 = 15;
+    const LINES_PER_LEVEL: usize = 10;
+
+    pub fn new(start_level: u8, endless: bool) -> Self {
+        let start_level = start_level.clamp(1, Self::MAX_START_LEVEL);
+        Marathon {
+            start_level,
+            goal: Self::GOAL,
+            endless,
+            timing: Timing::default(),
+            score: Score::default(),
+            level: start_level,
+        }
+    }
+}
+
+impl Default for Marathon {
+    fn default() -> Self {
+        Self::new(1, false)
+    }
+}
+
+impl Mode for Marathon {
+    fn name(&self) -> &'static str {
+        "marathon"
+    }
+
+    fn start(&mut self, engine: &mut Engine) {
+        engine.set_timing(self.timing);
+        engine.set_level_progression(self.start_level, Self::LINES_PER_LEVEL);
+        self.level = engine.level();
+    }
+
+    fn locked(&mut self, engine: &mut Engine, _stats: &Stats, clear: &LineClear) {
+        self.score.clear(clear, self.level, 1.0);
+        self.level = engine.level();
+    }
+
+    fn is_over(&self, stats: &Stats) -> bool {
+        !self.endless && stats.lines >= self.goal
+    }
+
+    fn panel(&self, stats: &Stats) -> Vec<String> {
+        let lines = match self.endless {
+            true => format!("LINES {}", stats.lines),
+            false => format!("LINES {}/{}", stats.lines.min(self.goal), self.goal),
+        };
+        vec![
+            format!("TIME {}", format_time(stats.time)),
+            format!("SCORE {}", self.score.total),
+            format!("LEVEL {}", self.level),
+            lines,
+        ]
+    }
+
+    fn finish(&mut self, stats: &Stats, _replay: &Replay) -> Vec<String> {
+        let mut result = match self.is_over(stats) {
+            true => vec![format!("Cleared {} lines with {} points in {}", self.goal, self.score.total, format_time(stats.time))],
+            false => vec![format!("Game over at level {} with {} points", self.level, self.score.total)],
+        };
+        result.push(format!("{} lines, {:.2} pieces per second", stats.lines, stats.pps()));
+        result.extend(self.score.breakdown());
+        result
+    }
+
+    fn retry(&self) -> Option<Box<dyn Mode>> {
+        Some(Box::new(Marathon { goal: self.goal, timing: self.timing, ..Marathon::new(self.start_level, self.endless) }))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+    use crate::mode::test::{bot_play, seeded};
+
+    #[test]
+    fn lines_drive_gravity() {
+        let marathon = Marathon { goal: 25, ..Marathon::new(3, false) };
+        let mut game = seeded(9, Box::new(marathon));
+        let start_drop = game.engine.drop_time();
+        bot_play(&mut game, usize::MAX, Duration::from_millis(40));
+        assert!(!game.engine.is_topped_out());
+        assert_eq!(game.engine.level(), 3 + game.stats.lines as u8 / 10);
+        assert!(game.engine.drop_time() < start_drop);
+        assert!(game.finish().unwrap()[0].starts_with("Cleared 25 lines"));
+    }
+}
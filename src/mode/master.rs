@@ -0,0 +1,279 @@
+use std::time::Duration;
+use crate::engine::{Engine, FRAME, LineClear, Timing, Visibility};
+use super::{Mode, Stats, format_time, replay::Replay};
+
+/// Levels from the given one on, TGM gravity in 1/256 rows per frame.
+const GRAVITY: [(u16, u32); 30] = [
+    (0, 4), (30, 6), (35, 8), (40, 10), (50, 12), (60, 16), (70, 32), (80, 48), (90, 64), (100, 80),
+    (120, 96), (140, 112), (160, 128), (170, 144), (200, 4), (220, 32), (230, 64), (233, 96), (236, 128), (239, 160),
+    (243, 192), (247, 224), (251, 256), (300, 512), (330, 768), (360, 1024), (400, 1280), (420, 1024), (450, 768), (500, 5120),
+];
+
+/// Levels from the given one on, ARE, line clear and lock delays in frames.
+const DELAYS: [(u16, u32, u32, u32); 6] = [
+    (0, 25, 40, 30),
+    (500, 25, 25, 30),
+    (600, 25, 16, 30),
+    (700, 16, 12, 30),
+    (800, 12, 6, 30),
+    (900, 12, 6, 17),
+];
+
+const GRADES: [&str; 18] = ["9", "8", "7", "6", "5", "4", "3", "2", "1", "S1", "S2", "S3", "S4", "S5", "S6", "S7", "S8", "S9"];
+
+/// Level, time and lowest grade to be on the way to GM, from TGM.
+const GM_CHECKS: [(u16, Duration, usize); 3] = [
+    (300, Duration::from_secs(255), 8),
+    (500, Duration::from_secs(450), 12),
+    (999, Duration::from_secs(810), 17),
+];
+
+/// Grade points for 1 to 4 lines, by internal grade.
+fn grade_points(grade: usize, lines: usize) -> f64 {
+    let points = match grade {
+        0..=4 => [10, 20, 40, 50],
+        5..=9 => [5, 15, 30, 40],
+        10..=14 => [2, 12, 25, 35],
+        _ => [2, 10, 20, 30],
+    };
+    points[lines.clamp(1, 4) - 1] as f64
+}
+
+/// Hidden grade, TGM2 style: clears fill grade points, a full bar raises the
+/// internal grade, and points drain while no combo is going.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Grading {
+    grade: usize,
+    points: f64,
+    /// Game time the next point drains at.
+    decay_at: Duration,
+    combo: bool,
+}
+
+impl Grading {
+    const BAR: f64 = 100.0;
+
+    /// Time per point drained, faster at higher grades.
+    fn decay(&self) -> Duration {
+        FRAME * 125_u32.saturating_sub(self.grade as u32 * 6).max(20)
+    }
+
+    fn clear(&mut self, clear: &LineClear, level: u16, time: Duration) {
+        self.combo = clear.lines > 0;
+        if clear.lines == 0 {
+            self.decay_at = time + self.decay();
+            return;
+        }
+        let combo = 1.0 + (clear.combo.min(9) as f64 * 0.1);
+        let level = 1.0 + (level / 250) as f64;
+        self.points += (grade_points(self.grade, clear.lines) * combo * level).ceil();
+        while self.points >= Self::BAR && self.grade < GRADES.len() - 1 {
+            self.points -= Self::BAR;
+            self.grade += 1;
+        }
+        self.points = self.points.min(Self::BAR - 1.0);
+    }
+
+    fn tick(&mut self, time: Duration) {
+        while !self.combo && time >= self.decay_at {
+            self.points = (self.points - 1.0).max(0.0);
+            self.decay_at += self.decay();
+        }
+    }
+}
+
+/// TGM Master: levels 0 to 999 speed up to 20G and shorter delays,
+/// a hidden grade rates the play and a credit roll follows, with a fading
+/// stack or an invisible one on the way to GM.
+pub struct Master {
+    level: u16,
+    grading: Grading,
+    /// Time every finished section took.
+    sections: Vec<Duration>,
+    /// Still meets every GM check so far.
+    gm_eligible: bool,
+    /// Game time the credit roll started at.
+    roll: Option<Duration>,
+}
+
+impl Master {
+    pub const FINAL_LEVEL: u16 = 999;
+    const SECTION: u16 = 100;
+    /// Sections slower than this are marked as regrets and cost a grade.
+    const REGRET: Duration = Duration::from_secs(90);
+    const ROLL: Duration = Duration::from_secs(55);
+    /// Fade out time of the stack in a roll without GM.
+    const ROLL_FADE: Duration = Duration::from_secs(4);
+
+    pub fn new() -> Self {
+        Master { level: 0, grading: Grading::default(), sections: Vec::new(), gm_eligible: true, roll: None }
+    }
+
+    fn timing(level: u16) -> Timing {
+        let (_, gravity) = *GRAVITY.iter().rev().find(|(from, _)| level >= *from).unwrap();
+        let (_, are, line_clear, lock_delay) = *DELAYS.iter().rev().find(|(from, ..)| level >= *from).unwrap();
+        Timing {
+            are: FRAME * are,
+            line_are: FRAME * are,
+            line_clear: FRAME * line_clear,
+            lock_delay: FRAME * lock_delay,
+            gravity: Some(gravity as f32 / 256.0),
+            initial_actions: true,
+        }
+    }
+
+    fn grade(&self) -> &'static str {
+        match self.roll.is_some() && self.gm_eligible {
+            true => "GM",
+            false => GRADES[self.grading.grade],
+        }
+    }
+
+    fn section_start(&self) -> Duration {
+        self.sections.iter().sum()
+    }
+
+    /// Levels from a piece and its clear. Pieces stop at the end of a section,
+    /// only clears move past it.
+    fn advance(&mut self, lines: usize) {
+        let stop = self.level % Self::SECTION == Self::SECTION - 1 || self.level == Self::FINAL_LEVEL - 1;
+        if !stop {
+            self.level += 1;
+        }
+        self.level = (self.level + lines as u16).min(Self::FINAL_LEVEL);
+    }
+}
+
+impl Default for Master {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Mode for Master {
+    fn name(&self) -> &'static str {
+        "master"
+    }
+
+    fn start(&mut self, engine: &mut Engine) {
+        engine.set_timing(Self::timing(self.level));
+    }
+
+    fn tick(&mut self, _engine: &mut Engine, stats: &Stats) {
+        self.grading.tick(stats.time);
+    }
+
+    fn locked(&mut self, engine: &mut Engine, stats: &Stats, clear: &LineClear) {
+        if self.roll.is_some() {
+            return;
+        }
+        self.grading.clear(clear, self.level, stats.time);
+        let before = self.level;
+        self.advance(clear.lines);
+        engine.set_timing(Self::timing(self.level));
+
+        if self.level / Self::SECTION > before / Self::SECTION || self.level == Self::FINAL_LEVEL {
+            let time = stats.time - self.section_start();
+            if time > Self::REGRET {
+                self.grading.grade = self.grading.grade.saturating_sub(1);
+            }
+            self.sections.push(time);
+        }
+        for (level, limit, grade) in GM_CHECKS {
+            if before < level && self.level >= level {
+                self.gm_eligible &= stats.time <= limit && self.grading.grade >= grade;
+            }
+        }
+
+        if self.level == Self::FINAL_LEVEL {
+            self.roll = Some(stats.time);
+            engine.clear_stack();
+            engine.set_visibility(match self.gm_eligible {
+                true => Visibility::Invisible,
+                false => Visibility::Fading(Self::ROLL_FADE),
+            });
+        }
+    }
+
+    fn is_over(&self, stats: &Stats) -> bool {
+        self.roll.is_some_and(|start| stats.time >= start + Self::ROLL)
+    }
+
+    fn panel(&self, stats: &Stats) -> Vec<String> {
+        let next = (self.level / Self::SECTION + 1) * Self::SECTION;
+        let mut panel = vec![
+            format!("GRADE {}", self.grade()),
+            format!("LEVEL {}/{}", self.level, next.min(Self::FINAL_LEVEL)),
+            format!("TIME {}", format_time(stats.time)),
+        ];
+        if let Some(start) = self.roll {
+            panel.push(format!("ROLL {}", format_time((start + Self::ROLL).saturating_sub(stats.time))));
+        }
+        for (i, time) in self.sections.iter().enumerate().rev().take(5) {
+            let mark = if *time > Self::REGRET { " R" } else { "" };
+            panel.push(format!("{:>3} {}{mark}", i * 100, format_time(*time)));
+        }
+        panel
+    }
+
+    fn finish(&mut self, stats: &Stats, _replay: &Replay) -> Vec<String> {
+        let mut result = vec![format!("Grade {}", self.grade())];
+        result.push(match self.roll {
+            Some(start) if stats.time >= start + Self::ROLL => format!("Cleared the credit roll in {}", format_time(stats.time)),
+            Some(_) => "Topped out in the credit roll".to_owned(),
+            None => format!("Topped out at level {} in {}", self.level, format_time(stats.time)),
+        });
+        for (i, time) in self.sections.iter().enumerate() {
+            let mark = if *time > Self::REGRET { "  regret" } else { "" };
+            result.push(format!("{:>3}-{:<3} {}{mark}", i * 100, i * 100 + 99, format_time(*time)));
+        }
+        result
+    }
+
+    fn retry(&self) -> Option<Box<dyn Mode>> {
+        Some(Box::new(Master::new()))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn levels_delays_and_grades() {
+        let mut master = Master::new();
+        let mut engine = Engine::with_seed(1);
+        master.start(&mut engine);
+        assert_eq!(engine.timing().are, FRAME * 25);
+
+        let mut stats = Stats::default();
+        let single = LineClear { lines: 1, ..LineClear::default() };
+        for _ in 0..120 {
+            stats.time += Duration::from_millis(500);
+            master.locked(&mut engine, &stats, &LineClear::default());
+        }
+        assert_eq!(master.level, 99, "pieces stop at the section end");
+        stats.time += Duration::from_millis(500);
+        master.locked(&mut engine, &stats, &single);
+        assert_eq!(master.level, 100);
+        assert_eq!(master.sections, [stats.time]);
+
+        master.level = 499;
+        stats.time = Duration::from_secs(300);
+        master.locked(&mut engine, &stats, &LineClear { lines: 4, combo: 3, ..LineClear::default() });
+        assert_eq!(engine.timing().gravity, Some(Timing::MAX_GRAVITY));
+        assert_eq!(engine.timing().line_clear, FRAME * 25);
+        assert!(master.grading.points > 0.0);
+
+        master.grading.combo = false;
+        master.grading.tick(stats.time + master.grading.decay() * 1000);
+        assert_eq!(master.grading.points, 0.0);
+
+        master.level = 998;
+        master.locked(&mut engine, &stats, &single);
+        assert!(master.roll.is_some() && !master.gm_eligible);
+        assert_eq!(engine.visibility(), Visibility::Fading(Master::ROLL_FADE));
+        assert!(master.is_over(&Stats { time: stats.time + Master::ROLL, ..Stats::default() }));
+    }
+}
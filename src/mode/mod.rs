@@ -0,0 +1,323 @@
+//! Single player modes: rules layered over an `Engine` deciding when a
+//! game ends and what the score panel shows.
+
+use std::{path::PathBuf, time::Duration};
+use crate::engine::{Color, Coordinate, Engine, LineClear, Matrix, piece::Piece};
+use self::replay::{Play, Replay};
+
+pub use self::dig::Dig;
+pub use self::marathon::Marathon;
+pub use self::master::Master;
+pub use self::practice::{Practice, Setup};
+pub use self::puzzle::{Puzzle, Puzzles};
+pub use self::sprint::Sprint;
+pub use self::ultra::Ultra;
+
+mod dig;
+pub mod finesse;
+mod marathon;
+mod master;
+mod practice;
+pub mod puzzle;
+pub mod replay;
+pub mod score;
+mod sprint;
+mod ultra;
+
+
+/// Counters every mode gets for free.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Stats {
+    /// Game time of the latest play or tick.
+    pub time: Duration,
+    pub pieces: usize,
+    pub lines: usize,
+    /// Moves and rotations, held moves count once.
+    pub inputs: usize,
+    /// Pieces placed with more inputs than needed.
+    pub finesse_faults: usize,
+    /// Finesse per kind, pieces needing soft drops aside.
+    pub finesse: Vec<finesse::Tally>,
+}
+
+impl Stats {
+    pub fn pps(&self) -> f32 {
+        match self.time.is_zero() {
+            true => 0.0,
+            false => self.pieces as f32 / self.time.as_secs_f32(),
+        }
+    }
+}
+
+
+pub trait Mode {
+    /// Names the mode in replays and their file names.
+    fn name(&self) -> &'static str;
+
+    /// Sets the engine up before the first piece.
+    fn start(&mut self, _engine: &mut Engine) {}
+
+    /// Called as game time passes, `stats.time` is the new time.
+    fn tick(&mut self, _engine: &mut Engine, _stats: &Stats) {}
+
+    /// Called for every locked piece, after `stats` counted it.
+    fn locked(&mut self, _engine: &mut Engine, _stats: &Stats, _clear: &LineClear) {}
+
+    /// Whether the goal is reached, topping out or running out of time
+    /// always ends the game.
+    fn is_over(&self, stats: &Stats) -> bool;
+
+    /// Score panel text, a line per entry.
+    fn panel(&self, stats: &Stats) -> Vec<String>;
+
+    /// Called once when the game ends, returns the result to show.
+    fn finish(&mut self, stats: &Stats, replay: &Replay) -> Vec<String>;
+
+    /// Cells drawn translucent over the matrix, like a stack to build.
+    fn overlay(&self, _engine: &Engine) -> Vec<(Coordinate, Color)> {
+        Vec::new()
+    }
+
+    /// A fresh copy for another try, for modes that offer one.
+    fn retry(&self) -> Option<Box<dyn Mode>> {
+        None
+    }
+
+    /// What to play after this one, for modes made of stages.
+    fn next(&self) -> Option<Box<dyn Mode>> {
+        None
+    }
+}
+
+/// Plays on until topping out.
+pub struct Free;
+
+impl Mode for Free {
+    fn name(&self) -> &'static str {
+        "free"
+    }
+
+    fn is_over(&self, _stats: &Stats) -> bool {
+        false
+    }
+
+    fn panel(&self, stats: &Stats) -> Vec<String> {
+        vec![
+            format!("TIME {}", format_time(stats.time)),
+            format!("LINES {}", stats.lines),
+            format!("PIECES {}", stats.pieces),
+        ]
+    }
+
+    fn finish(&mut self, stats: &Stats, _replay: &Replay) -> Vec<String> {
+        vec![format!("{} lines in {}", stats.lines, format_time(stats.time))]
+    }
+
+    fn retry(&self) -> Option<Box<dyn Mode>> {
+        Some(Box::new(Free))
+    }
+}
+
+
+/// The current piece as it spawned, with the matrix it spawned over and
+/// the inputs spent on it so far.
+struct Spawned {
+    piece: Piece,
+    matrix: Matrix,
+    inputs: usize,
+}
+
+/// An engine played under a mode, keeping stats and a replay.
+pub struct Game {
+    pub engine: Engine,
+    /// The engine as it was handed over, for retries.
+    initial: Engine,
+    pub mode: Box<dyn Mode>,
+    pub stats: Stats,
+    pub replay: Replay,
+    spawned: Option<Spawned>,
+    finished: bool,
+    /// Start over whenever a piece locks with a finesse fault.
+    pub restart_on_fault: bool,
+    /// The latest locked piece was a finesse fault.
+    faulted: bool,
+}
+
+impl Game {
+    /// `seed` is what `engine` was made with, kept for the replay.
+    pub fn new(mut engine: Engine, seed: u64, mut mode: Box<dyn Mode>) -> Self {
+        let initial = engine.clone();
+        mode.start(&mut engine);
+        let mut game = Game {
+            engine,
+            initial,
+            replay: Replay::new(seed, mode.name()),
+            mode,
+            stats: Stats::default(),
+            spawned: None,
+            finished: false,
+            restart_on_fault: false,
+            faulted: false,
+        };
+        game.spawn();
+        game
+    }
+
+    fn spawn(&mut self) {
+        if self.engine.cursor().is_none() && !self.engine.is_topped_out() && self.engine.is_spawn_ready() {
+            let _ = self.engine.spawn();
+        }
+        self.track();
+    }
+
+    fn track(&mut self) {
+        self.spawned = self.engine.cursor().map(|piece| Spawned { piece, matrix: *self.engine.matrix(), inputs: 0 });
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.engine.is_topped_out() || self.engine.is_time_up() || self.mode.is_over(&self.stats)
+    }
+
+    /// Advances the clock to game time `time`, unless the game is over.
+    pub fn tick(&mut self, time: Duration) {
+        if !self.is_over() {
+            self.engine.advance_clock(time);
+            self.stats.time = self.engine.clock();
+            self.mode.tick(&mut self.engine, &self.stats);
+            if self.engine.cursor().is_none() && !self.is_over() {
+                self.spawn();
+            }
+        }
+    }
+
+    /// Applies `play` at game time `time`, returns the clear when a piece locked.
+    pub fn play(&mut self, time: Duration, play: Play) -> Option<LineClear> {
+        if self.is_over() {
+            return None;
+        }
+        self.tick(time);
+        self.replay.plays.push((time, play));
+
+        if let (Play::Move(_) | Play::Rotate(_), Some(spawned)) = (play, &mut self.spawned) {
+            spawned.inputs += 1;
+            self.stats.inputs += 1;
+        }
+        let landed = self.engine.cursor().map(|cursor| finesse::dropped(self.engine.matrix(), cursor));
+        let swaps = play == Play::Hold && self.engine.can_hold();
+
+        if !play.apply(&mut self.engine) {
+            if swaps {
+                self.track();
+            }
+            return None;
+        }
+
+        self.faulted = false;
+        if let (Some(spawned), Some(landed)) = (self.spawned.take(), landed) {
+            if let Some(minimal) = finesse::minimal_inputs(&spawned.matrix, spawned.piece, &landed) {
+                self.faulted = spawned.inputs > minimal;
+                self.stats.finesse_faults += self.faulted as usize;
+                finesse::record(&mut self.stats.finesse, spawned.piece.kind, self.faulted);
+            }
+        }
+
+        let clear = self.engine.line_clear(|_| ())?;
+        self.stats.pieces += 1;
+        self.stats.lines += clear.lines;
+        self.mode.locked(&mut self.engine, &self.stats, &clear);
+        if !self.is_over() {
+            self.spawn();
+        }
+        Some(clear)
+    }
+
+    /// Whether the game wants starting over, see `restart_on_fault`.
+    pub fn should_restart(&self) -> bool {
+        self.restart_on_fault && self.faulted
+    }
+
+    /// The same game from the start, if the mode allows retries.
+    pub fn retry(&self) -> Option<Game> {
+        let mut game = Game::new(self.initial.clone(), self.replay.seed, self.mode.retry()?);
+        game.restart_on_fault = self.restart_on_fault;
+        game.replay.args.clone_from(&self.replay.args);
+        Some(game)
+    }
+
+    /// The mode's next stage, on the same engine settings.
+    pub fn next(&self) -> Option<Game> {
+        let mut game = Game::new(self.initial.clone(), self.replay.seed, self.mode.next()?);
+        game.restart_on_fault = self.restart_on_fault;
+        game.replay.args.clone_from(&self.replay.args);
+        Some(game)
+    }
+
+    /// The mode's score panel, finesse faults included.
+    pub fn panel(&self) -> Vec<String> {
+        let mut panel = self.mode.panel(&self.stats);
+        panel.push(format!("FAULTS {}", self.stats.finesse_faults));
+        panel
+    }
+
+    /// Wraps up once the game is over, returning the mode's result the first time.
+    pub fn finish(&mut self) -> Option<Vec<String>> {
+        if !self.is_over() || std::mem::replace(&mut self.finished, true) {
+            return None;
+        }
+        self.replay.time = self.stats.time;
+        let mut result = self.mode.finish(&self.stats, &self.replay);
+        if !self.stats.finesse.is_empty() {
+            result.push(format!("Finesse faults: {}", finesse::breakdown(&self.stats.finesse)));
+        }
+        Some(result)
+    }
+}
+
+
+/// Where records and replays are kept: `$TETRIS_DATA`, else `~/.tetris`.
+pub fn data_dir() -> PathBuf {
+    std::env::var_os("TETRIS_DATA")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".tetris")))
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Replay of the latest finished game of the mode named `mode`.
+pub fn last_replay_path(mode: &str) -> PathBuf {
+    data_dir().join(format!("{mode}.last.replay"))
+}
+
+/// `m:ss.mmm`
+pub fn format_time(time: Duration) -> String {
+    let millis = time.as_millis();
+    format!("{}:{:02}.{:03}", millis / 60_000, millis / 1000 % 60, millis % 1000)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bot::Bot;
+
+    /// A game of `mode` on an engine seeded with `seed`.
+    pub(super) fn seeded(seed: u64, mode: Box<dyn Mode>) -> Game {
+        Game::new(Engine::with_seed(seed), seed, mode)
+    }
+
+    /// Has a quick bot play `game` until it ends, or for `pieces` pieces at
+    /// most, a move every `step`. Returns the time of the last move.
+    pub(super) fn bot_play(game: &mut Game, pieces: usize, step: Duration) -> Duration {
+        let bot = Bot { lookahead: 0, ..Bot::default() };
+        let mut time = game.stats.time;
+        for _ in 0..pieces {
+            if game.is_over() {
+                break;
+            }
+            for action in bot.think(&game.engine).unwrap().actions {
+                time += step;
+                game.play(time, action.into());
+            }
+        }
+        time
+    }
+}
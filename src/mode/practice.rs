@@ -0,0 +1,292 @@
+//! Opener practice: build a setup from the first bags, over and over.
+//! Setup files are `name = value` lines, then the target stack in plain text:
+//!
+//! ```text
+//! name = Flat base
+//! bags = I?????? ?I?????
+//! [target]
+//! OO........
+//! OOIIII....
+//! ```
+//!
+//! Target cells are piece letters, so every piece has to go where its color
+//! shows. Several `[target]` boards give the variations a setup allows, each
+//! placement only has to agree with one of them. `bags` lists the orders the
+//! first bag may come in, `?` standing for any piece, and leaves every order
+//! in when missing. Targets can't hold full rows, setups are built without
+//! clearing lines. `#` starts a comment.
+
+use std::str::FromStr;
+use crate::bot::placements;
+use crate::engine::{Color, Coordinate, Engine, LineClear, Matrix, notation, piece::{Kind as PieceKind, Piece}};
+use super::{Mode, Stats, replay::Replay};
+
+/// Redeals before giving up on the bag orders, should a piece set rule them all out.
+const MAX_DEALS: usize = 10_000;
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Setup {
+    pub name: String,
+    pub targets: Vec<Matrix>,
+    /// First bag orders, `None` for any piece.
+    pub bags: Vec<Vec<Option<PieceKind>>>,
+}
+
+impl Setup {
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self, String> {
+        std::fs::read_to_string(path)
+            .map_err(|error| error.to_string())?
+            .parse()
+    }
+
+    /// Whether the engine deals the first bag in one of the allowed orders.
+    fn allows(&self, engine: &Engine) -> bool {
+        let dealt = engine.queue().chain(engine.rest_of_bag()).collect::<Vec<_>>();
+        self.bags.is_empty() || self.bags.iter().any(|order| {
+            order.len() <= dealt.len() && order.iter().zip(&dealt).all(|(kind, dealt)| kind.is_none_or(|kind| kind == *dealt))
+        })
+    }
+}
+
+impl FromStr for Setup {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut settings = Vec::new();
+        let mut targets: Vec<String> = Vec::new();
+
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            match (line == "[target]", targets.last_mut()) {
+                (true, _) => targets.push(String::new()),
+                (false, Some(rows)) => {
+                    rows.push_str(line);
+                    rows.push('\n');
+                },
+                (false, None) => settings.push(line.split_once('=').ok_or_else(|| format!("expected `name = value`, got `{line}`"))?),
+            }
+        }
+
+        let setting = |name: &str| settings.iter()
+            .find(|(known, _)| known.trim() == name)
+            .map(|(_, value)| value.trim());
+
+        let targets = targets.iter().map(|rows| rows.parse::<Matrix>()).collect::<Result<Vec<_>, _>>()?;
+        if targets.is_empty() {
+            return Err("no [target] board".to_owned());
+        }
+        if targets.iter().any(|target| !target.full_lines().is_empty()) {
+            return Err("targets can't hold full rows".to_owned());
+        }
+
+        let bags = setting("bags")
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(|order| {
+                let order = order.chars()
+                    .map(|c| match c {
+                        '?' => Ok(None),
+                        c => notation::letter_kind(c).map(Some).ok_or_else(|| format!("unknown piece `{c}`")),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                let kinds = order.iter().flatten().collect::<Vec<_>>();
+                let repeats = kinds.iter().enumerate().any(|(index, kind)| kinds[..index].contains(kind));
+                match order.len() <= PieceKind::ALL.len() && !repeats {
+                    true => Ok(order),
+                    false => Err("a bag order holds each piece once at most".to_owned()),
+                }
+            })
+            .collect::<Result<_, String>>()?;
+
+        Ok(Setup {
+            name: setting("name").ok_or("missing `name`")?.to_owned(),
+            targets,
+            bags,
+        })
+    }
+}
+
+
+/// Whether `target` agrees with every cell `matrix` has filled.
+fn agrees(target: &Matrix, matrix: &Matrix) -> bool {
+    matrix.cells()
+        .zip(target.cells())
+        .all(|((_, cell, _), (_, goal, _))| cell.is_none() || cell == goal)
+}
+
+fn is_built(target: &Matrix, matrix: &Matrix) -> bool {
+    target.cells().zip(matrix.cells()).all(|((_, goal, _), (_, cell, _))| goal.is_none() || goal == cell)
+}
+
+/// Whether the rest of `target` can be built from `hold` and `queue`, as far
+/// as they go. Pieces past the queue are unknown, so running out counts.
+fn completable(target: &Matrix, matrix: &Matrix, hold: Option<PieceKind>, queue: &[PieceKind]) -> bool {
+    if is_built(target, matrix) {
+        return true;
+    }
+    let Some((&next, rest)) = queue.split_first() else {
+        return true;
+    };
+
+    let mut choices = vec![(next, hold, rest)];
+    match (hold, rest) {
+        (Some(held), _) => choices.push((held, Some(next), rest)),
+        (None, [after, rest @ ..]) => choices.push((*after, Some(next), rest)),
+        (None, []) => {},
+    }
+    choices.into_iter().any(|(kind, hold, queue)| {
+        placements(matrix, Piece::spawned(kind)).into_iter().any(|placement| {
+            let fits = placement.piece.cells().is_some_and(|cells| cells.iter().all(|&coord| target[coord] == Some(kind.color())));
+            let mut matrix = *matrix;
+            matrix.place(&placement.piece);
+            fits && completable(target, &matrix, hold, queue)
+        })
+    })
+}
+
+
+/// Builds a setup again and again, dealing a new bag after every success or
+/// as soon as a placement strays from all the setup's solutions.
+pub struct Practice {
+    setup: Setup,
+    /// The target the stack agrees with so far, shown under the stack.
+    target: usize,
+    built: usize,
+    misses: usize,
+    streak: usize,
+}
+
+impl Practice {
+    pub fn new(setup: Setup) -> Self {
+        Practice { setup, target: 0, built: 0, misses: 0, streak: 0 }
+    }
+
+    fn deal(&mut self, engine: &mut Engine) {
+        for _ in 0..MAX_DEALS {
+            if self.setup.allows(engine) {
+                break;
+            }
+            engine.restart();
+        }
+        self.target = 0;
+    }
+}
+
+impl Mode for Practice {
+    fn name(&self) -> &'static str {
+        "practice"
+    }
+
+    fn start(&mut self, engine: &mut Engine) {
+        self.deal(engine);
+    }
+
+    fn locked(&mut self, engine: &mut Engine, _stats: &Stats, _clear: &LineClear) {
+        let queue = engine.queue().collect::<Vec<_>>();
+        let target = self.setup.targets.iter().position(|target| {
+            agrees(target, engine.matrix()) && completable(target, engine.matrix(), engine.held(), &queue)
+        });
+
+        match target {
+            Some(target) if is_built(&self.setup.targets[target], engine.matrix()) => {
+                self.built += 1;
+                self.streak += 1;
+            },
+            Some(target) => {
+                self.target = target;
+                return;
+            },
+            None => {
+                self.misses += 1;
+                self.streak = 0;
+            },
+        }
+        engine.restart();
+        self.deal(engine);
+    }
+
+    fn is_over(&self, _stats: &Stats) -> bool {
+        false
+    }
+
+    fn panel(&self, _stats: &Stats) -> Vec<String> {
+        vec![
+            self.setup.name.to_uppercase(),
+            format!("BUILT {}", self.built),
+            format!("MISSES {}", self.misses),
+            format!("STREAK {}", self.streak),
+        ]
+    }
+
+    fn finish(&mut self, _stats: &Stats, _replay: &Replay) -> Vec<String> {
+        vec![format!("Built {} {} times, missed {}", self.setup.name, self.built, self.misses)]
+    }
+
+    fn overlay(&self, engine: &Engine) -> Vec<(Coordinate, Color)> {
+        self.setup.targets[self.target]
+            .cells()
+            .filter_map(|(coord, goal, _)| Some((coord, goal?)))
+            .filter(|(coord, _)| engine.matrix()[*coord].is_none())
+            .collect()
+    }
+
+    fn retry(&self) -> Option<Box<dyn Mode>> {
+        Some(Box::new(Practice::new(self.setup.clone())))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::engine::MoveKind;
+    use crate::mode::{Game, replay::Play};
+
+    const BASE: &str = "
+        name = Base
+        bags = IO
+        [target]
+        ....OO....
+        IIIIOO....
+    ";
+
+    #[test]
+    fn deals_allowed_bags() {
+        let setup = BASE.parse::<Setup>().unwrap();
+        for seed in 0..4 {
+            let game = Game::new(Engine::with_seed(seed), seed, Box::new(Practice::new(setup.clone())));
+            assert_eq!(game.engine.cursor().map(|cursor| cursor.kind), Some(PieceKind::I));
+            assert_eq!(game.engine.queue().next(), Some(PieceKind::O));
+            assert_eq!(game.mode.overlay(&game.engine).len(), 8);
+        }
+
+        assert!("name = a\n".parse::<Setup>().is_err());
+        assert!("name = a\nbags = II\n[target]\nIIII\n".parse::<Setup>().is_err());
+        assert!("name = a\n[target]\nIIIIIIIIII\n".parse::<Setup>().is_err());
+    }
+
+    #[test]
+    fn resets_on_deviation() {
+        let setup = BASE.parse::<Setup>().unwrap();
+        let mut game = Game::new(Engine::with_seed(1), 1, Box::new(Practice::new(setup)));
+        let time = std::time::Duration::ZERO;
+
+        game.play(time, Play::HardDrop);
+        assert_eq!(game.engine.matrix(), &Matrix::blank());
+        assert_eq!(game.mode.panel(&game.stats)[2], "MISSES 1");
+
+        for _ in 0..3 {
+            game.play(time, Play::Move(MoveKind::Left));
+        }
+        game.play(time, Play::HardDrop);
+        assert_eq!(game.mode.overlay(&game.engine).len(), 4);
+        game.play(time, Play::HardDrop);
+        assert_eq!(game.engine.matrix(), &Matrix::blank());
+        assert_eq!(game.mode.panel(&game.stats)[1], "BUILT 1");
+        assert_eq!(game.engine.cursor().map(|cursor| cursor.kind), Some(PieceKind::I));
+    }
+}
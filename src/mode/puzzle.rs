@@ -0,0 +1,327 @@
+//! Puzzles: a starting stack, a fixed piece sequence and a goal to reach with it.
+//! Puzzle files are `name = value` lines, then the boards in plain text:
+//!
+//! ```text
+//! name = First tetris
+//! pieces = I
+//! goal = lines 4
+//! [board]
+//! GGGGGGGGG.
+//! GGGGGGGGG.
+//! GGGGGGGGG.
+//! GGGGGGGGG.
+//! ```
+//!
+//! `goal` is `lines N`, `tspin [mini] single|double|triple`, `perfect clear`
+//! or `shape`, which needs a `[target]` board the stack has to match cell
+//! for cell, colors aside. `#` starts a comment.
+
+use std::{path::{Path, PathBuf}, str::FromStr};
+use crate::engine::{Engine, LineClear, Matrix, Spin, notation, piece::Kind as PieceKind};
+use super::{Mode, Stats, data_dir, replay::Replay};
+
+const BUNDLED: [&str; 4] = [
+    "
+    name = Tetris
+    pieces = I
+    goal = lines 4
+    [board]
+    GGGGGGGGG.
+    GGGGGGGGG.
+    GGGGGGGGG.
+    GGGGGGGGG.
+    ",
+    "
+    name = Stacking
+    pieces = OI
+    goal = shape
+    [target]
+    OO........
+    OOIIII....
+    ",
+    "
+    name = Two piece clear
+    pieces = LJ
+    goal = perfect clear
+    [board]
+    .GGGGGGGG.
+    ...GGGG...
+    ",
+    "
+    name = T-spin double
+    pieces = T
+    goal = tspin double
+    [board]
+    GG........
+    G...GGGGGG
+    GG.GGGGGGG
+    ",
+];
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Objective {
+    /// Clear this many lines in total.
+    Lines(usize),
+    /// One clear of this many lines by a T-spin.
+    TSpin(Spin, usize),
+    PerfectClear,
+    /// Filled and empty cells match the target, colors aside.
+    Shape(Box<Matrix>),
+}
+
+impl Objective {
+    /// Whether the piece that just locked, making `clear`, completes the objective.
+    /// `lines` counts every line cleared so far.
+    pub fn is_met(&self, engine: &Engine, clear: &LineClear, lines: usize) -> bool {
+        match self {
+            Objective::Lines(goal) => lines >= *goal,
+            Objective::TSpin(spin, goal) => clear.spin == Some(*spin) && clear.lines == *goal,
+            Objective::PerfectClear => clear.perfect_clear,
+            Objective::Shape(target) => engine.matrix()
+                .cells()
+                .zip(target.cells())
+                .all(|((_, cell, _), (_, goal, _))| cell.is_some() == goal.is_some()),
+        }
+    }
+
+    /// Fits a line of the score panel.
+    fn label(&self) -> String {
+        match self {
+            Objective::Lines(goal) => format!("{goal} LINES"),
+            Objective::TSpin(spin, goal) => {
+                let mini = if *spin == Spin::Mini { "M" } else { "" };
+                format!("T-SPIN {mini}{}", ["ZERO", "SINGLE", "DOUBLE", "TRIPLE"][(*goal).min(3)])
+            },
+            Objective::PerfectClear => "PERFECT CLEAR".to_owned(),
+            Objective::Shape(_) => "BUILD SHAPE".to_owned(),
+        }
+    }
+
+    fn parse(text: &str, target: Option<Matrix>) -> Result<Self, String> {
+        let words = text.split_whitespace().collect::<Vec<_>>();
+        let count = |word: &str| ["zero", "single", "double", "triple"].iter().position(|known| *known == word);
+
+        let objective = match words[..] {
+            ["lines", goal] => Objective::Lines(goal.parse().map_err(|_| format!("bad line count `{goal}`"))?),
+            ["tspin", "mini", lines] => Objective::TSpin(Spin::Mini, count(lines).ok_or("bad T-spin clear")?),
+            ["tspin", lines] => Objective::TSpin(Spin::Full, count(lines).ok_or("bad T-spin clear")?),
+            ["perfect", "clear"] => Objective::PerfectClear,
+            ["shape"] => Objective::Shape(Box::new(target.ok_or("`shape` needs a [target] board")?)),
+            _ => return Err(format!("unknown goal `{text}`")),
+        };
+        Ok(objective)
+    }
+}
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Puzzle {
+    pub name: String,
+    pub matrix: Matrix,
+    pub pieces: Vec<PieceKind>,
+    pub objective: Objective,
+}
+
+impl Puzzle {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        std::fs::read_to_string(path)
+            .map_err(|error| error.to_string())?
+            .parse()
+    }
+
+    /// The puzzles that come with the game.
+    pub fn bundled() -> Vec<Self> {
+        BUNDLED.iter()
+            .map(|text| text.parse().expect("Fail to parse a bundled puzzle"))
+            .collect()
+    }
+
+    /// Where user puzzles go, played after the bundled ones.
+    pub fn user_dir() -> PathBuf {
+        data_dir().join("puzzles")
+    }
+
+    /// Every `.puzzle` file in `dir`, by file name.
+    pub fn load_dir(dir: impl AsRef<Path>) -> Result<Vec<Self>, String> {
+        let mut paths = std::fs::read_dir(dir)
+            .map_err(|error| error.to_string())?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|extension| extension == "puzzle"))
+            .collect::<Vec<_>>();
+        paths.sort();
+        paths.iter()
+            .map(|path| Self::load(path).map_err(|error| format!("{}: {error}", path.display())))
+            .collect()
+    }
+}
+
+impl FromStr for Puzzle {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut settings = Vec::new();
+        let mut boards: Vec<(&str, String)> = Vec::new();
+
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            match (line.strip_prefix('[').and_then(|line| line.strip_suffix(']')), boards.last_mut()) {
+                (Some(board), _) => boards.push((board, String::new())),
+                (None, Some((_, rows))) => {
+                    rows.push_str(line);
+                    rows.push('\n');
+                },
+                (None, None) => settings.push(line.split_once('=').ok_or_else(|| format!("expected `name = value`, got `{line}`"))?),
+            }
+        }
+
+        let setting = |name: &str| settings.iter()
+            .find(|(known, _)| known.trim() == name)
+            .map(|(_, value)| value.trim())
+            .ok_or_else(|| format!("missing `{name}`"));
+        let board = |name: &str| boards.iter()
+            .find(|(known, _)| *known == name)
+            .map(|(_, rows)| rows.parse::<Matrix>())
+            .transpose();
+
+        let pieces = setting("pieces")?
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .map(|c| notation::letter_kind(c).ok_or_else(|| format!("unknown piece `{c}`")))
+            .collect::<Result<Vec<_>, _>>()?;
+        if pieces.is_empty() {
+            return Err("no pieces".to_owned());
+        }
+
+        Ok(Puzzle {
+            name: setting("name")?.to_owned(),
+            matrix: board("board")?.unwrap_or_else(Matrix::blank),
+            pieces,
+            objective: Objective::parse(setting("goal")?, board("target")?)?,
+        })
+    }
+}
+
+
+/// Plays one puzzle of a pack, retrying it or moving on to the next.
+pub struct Puzzles {
+    pack: Vec<Puzzle>,
+    index: usize,
+    solved: bool,
+    /// Ran out of pieces without solving it.
+    failed: bool,
+}
+
+impl Puzzles {
+    pub fn new(pack: Vec<Puzzle>, index: usize) -> Self {
+        assert!(index < pack.len(), "no puzzle {} in a pack of {}", index + 1, pack.len());
+        Puzzles { pack, index, solved: false, failed: false }
+    }
+
+    pub fn puzzle(&self) -> &Puzzle {
+        &self.pack[self.index]
+    }
+}
+
+impl Mode for Puzzles {
+    fn name(&self) -> &'static str {
+        "puzzle"
+    }
+
+    fn start(&mut self, engine: &mut Engine) {
+        engine.set_matrix(self.puzzle().matrix);
+        engine.set_sequence(&self.puzzle().pieces);
+    }
+
+    fn locked(&mut self, engine: &mut Engine, stats: &Stats, clear: &LineClear) {
+        self.solved |= self.puzzle().objective.is_met(engine, clear, stats.lines);
+        self.failed = !self.solved && stats.pieces >= self.puzzle().pieces.len();
+    }
+
+    fn is_over(&self, _stats: &Stats) -> bool {
+        self.solved || self.failed
+    }
+
+    fn panel(&self, stats: &Stats) -> Vec<String> {
+        let mut panel = vec![
+            format!("PUZZLE {}/{}", self.index + 1, self.pack.len()),
+            self.puzzle().objective.label(),
+            format!("PIECES {}", self.puzzle().pieces.len().saturating_sub(stats.pieces)),
+        ];
+        match (self.solved, self.failed) {
+            (true, _) => panel.extend(["SOLVED".to_owned(), "N NEXT".to_owned()]),
+            (_, true) => panel.extend(["FAILED".to_owned(), "R RETRY".to_owned()]),
+            _ => {},
+        }
+        panel
+    }
+
+    fn finish(&mut self, _stats: &Stats, _replay: &Replay) -> Vec<String> {
+        let outcome = if self.solved { "Solved" } else { "Failed" };
+        vec![
+            format!("{outcome} puzzle {}: {}", self.index + 1, self.puzzle().name),
+            "R to retry, N for the next puzzle".to_owned(),
+        ]
+    }
+
+    fn retry(&self) -> Option<Box<dyn Mode>> {
+        Some(Box::new(Puzzles::new(self.pack.clone(), self.index)))
+    }
+
+    fn next(&self) -> Option<Box<dyn Mode>> {
+        (self.index + 1 < self.pack.len()).then(|| Box::new(Puzzles::new(self.pack.clone(), self.index + 1)) as Box<dyn Mode>)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bot::{Action, placements};
+    use crate::mode::{Game, replay::Play};
+
+    /// Tries every placement of every piece in turn, hold aside.
+    fn solvable(engine: &Engine, objective: &Objective, lines: usize) -> bool {
+        let Some(cursor) = engine.cursor() else {
+            return false;
+        };
+        placements(engine.matrix(), cursor).into_iter().any(|placement| {
+            let mut engine = engine.clone();
+            for action in placement.actions.iter().chain([&Action::HardDrop]) {
+                let _ = action.apply(&mut engine);
+            }
+            let clear = engine.line_clear(|_| ()).unwrap();
+            let lines = lines + clear.lines;
+            objective.is_met(&engine, &clear, lines)
+                || (engine.spawn().is_ok() && solvable(&engine, objective, lines))
+        })
+    }
+
+    #[test]
+    fn bundled_puzzles_are_solvable() {
+        for puzzle in Puzzle::bundled() {
+            let game = Game::new(Engine::with_seed(0), 0, Box::new(Puzzles::new(vec![puzzle.clone()], 0)));
+            assert_eq!(game.engine.cursor().map(|cursor| cursor.kind), Some(puzzle.pieces[0]));
+            assert!(solvable(&game.engine, &puzzle.objective, 0), "{} can't be solved", puzzle.name);
+        }
+    }
+
+    #[test]
+    fn retry_and_next() {
+        let mut game = Game::new(Engine::with_seed(0), 0, Box::new(Puzzles::new(Puzzle::bundled(), 0)));
+        game.play(std::time::Duration::ZERO, Play::HardDrop);
+        assert!(game.is_over());
+        assert_eq!(game.finish().unwrap()[0], "Failed puzzle 1: Tetris");
+
+        let retried = game.retry().unwrap();
+        assert!(!retried.is_over());
+        assert_eq!(retried.engine.matrix(), &Puzzle::bundled()[0].matrix);
+        assert_eq!(game.next().unwrap().mode.panel(&Stats::default())[0], "PUZZLE 2/4");
+
+        assert!("name = a\npieces = T\ngoal = shape\n".parse::<Puzzle>().is_err());
+        assert!("name = a\npieces = Q\ngoal = lines 1\n".parse::<Puzzle>().is_err());
+    }
+}
@@ -0,0 +1,172 @@
+use std::{fmt, str::FromStr, time::Duration};
+use crate::bot::Action;
+use crate::engine::{Engine, MoveKind, piece::RotateKind};
+
+/// Everything that can happen to the cursor, inputs and gravity alike,
+/// so feeding the same plays at the same times replays a game exactly.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Play {
+    Move(MoveKind),
+    /// Move from a held key, not counted as an input.
+    Repeat(MoveKind),
+    Rotate(RotateKind),
+    SoftDrop,
+    /// Soft drop all the way down.
+    SonicDrop,
+    HardDrop,
+    Hold,
+    /// Falls, or locks a cursor resting for the lock delay.
+    Gravity,
+}
+
+impl Play {
+    const NAMES: [(Play, &'static str); 10] = [
+        (Play::Move(MoveKind::Left), "left"),
+        (Play::Move(MoveKind::Right), "right"),
+        (Play::Repeat(MoveKind::Left), "repeat_left"),
+        (Play::Repeat(MoveKind::Right), "repeat_right"),
+        (Play::Rotate(RotateKind::Clockwise), "cw"),
+        (Play::Rotate(RotateKind::CounterClockwise), "ccw"),
+        (Play::SoftDrop, "soft"),
+        (Play::SonicDrop, "sonic"),
+        (Play::HardDrop, "hard"),
+        (Play::Hold, "hold"),
+    ];
+
+    fn name(&self) -> &'static str {
+        Self::NAMES.iter()
+            .find(|(play, _)| play == self)
+            .map_or("gravity", |(_, name)| name)
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "gravity" => Some(Play::Gravity),
+            name => Self::NAMES.iter().find(|(_, known)| *known == name).map(|(play, _)| *play),
+        }
+    }
+
+    /// Applies the play, returns whether a piece locked.
+    pub fn apply(&self, engine: &mut Engine) -> bool {
+        match *self {
+            Play::Move(kind) | Play::Repeat(kind) => drop(engine.move_cursor(kind)),
+            Play::Rotate(kind) => drop(engine.rotate_cursor(kind)),
+            Play::SoftDrop => drop(engine.soft_drop()),
+            Play::SonicDrop => while engine.soft_drop() {},
+            Play::Hold => drop(engine.hold()),
+            Play::HardDrop if engine.cursor().is_some() => {
+                engine.hard_drop();
+                return true;
+            },
+            Play::Gravity => return engine.fall(),
+            Play::HardDrop => {},
+        }
+        false
+    }
+}
+
+impl From<Action> for Play {
+    fn from(action: Action) -> Self {
+        match action {
+            Action::Move(kind) => Play::Move(kind),
+            Action::Rotate(kind) => Play::Rotate(kind),
+            Action::SonicDrop => Play::SonicDrop,
+            Action::HardDrop => Play::HardDrop,
+            Action::Hold => Play::Hold,
+        }
+    }
+}
+
+
+/// A seed and the timed plays of one game.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Replay {
+    pub seed: u64,
+    /// `Mode::name` of the game.
+    pub mode: String,
+    /// Command line options the engine and mode were set up from.
+    pub args: Vec<String>,
+    /// Final game time, what records are ranked by.
+    pub time: Duration,
+    pub plays: Vec<(Duration, Play)>,
+}
+
+impl Replay {
+    pub fn new(seed: u64, mode: &str) -> Self {
+        Replay { seed, mode: mode.to_owned(), ..Self::default() }
+    }
+
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self, String> {
+        std::fs::read_to_string(path)
+            .map_err(|error| error.to_string())?
+            .parse()
+    }
+
+    pub fn save(&self, path: impl AsRef<std::path::Path>) -> Result<(), String> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|error| error.to_string())?;
+        }
+        std::fs::write(path, self.to_string()).map_err(|error| error.to_string())
+    }
+}
+
+/// `seed`, `mode`, an `arg` per option and `time` (in microseconds) as
+/// `name = value` lines, then one `<microseconds> <play>` line per play.
+impl fmt::Display for Replay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "seed = {}", self.seed)?;
+        writeln!(f, "mode = {}", self.mode)?;
+        for arg in &self.args {
+            writeln!(f, "arg = {arg}")?;
+        }
+        writeln!(f, "time = {}", self.time.as_micros())?;
+        for (time, play) in &self.plays {
+            writeln!(f, "{} {}", time.as_micros(), play.name())?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Replay {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut replay = Replay::default();
+        let micros = |value: &str| value.trim().parse().map(Duration::from_micros).map_err(|_| format!("bad time `{value}`"));
+
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            if let Some((name, value)) = line.split_once('=') {
+                match name.trim() {
+                    "seed" => replay.seed = value.trim().parse().map_err(|_| format!("bad seed `{value}`"))?,
+                    "mode" => replay.mode = value.trim().to_owned(),
+                    "arg" => replay.args.push(value.trim().to_owned()),
+                    "time" => replay.time = micros(value)?,
+                    name => return Err(format!("unknown setting `{name}`")),
+                }
+                continue;
+            }
+            let (time, play) = line.split_once(' ').ok_or_else(|| format!("expected `time play`, got `{line}`"))?;
+            let play = Play::parse(play.trim()).ok_or_else(|| format!("unknown play `{play}`"))?;
+            replay.plays.push((micros(time)?, play));
+        }
+        Ok(replay)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn replay_file_round_trip() {
+        let replay = Replay {
+            args: vec!["--big".to_owned(), "--minutes".to_owned(), "3".to_owned()],
+            time: Duration::from_millis(1500),
+            plays: vec![(Duration::ZERO, Play::Move(MoveKind::Left)), (Duration::from_millis(1500), Play::HardDrop)],
+            ..Replay::new(42, "ultra")
+        };
+        assert_eq!(replay.to_string().parse::<Replay>(), Ok(replay));
+    }
+}
@@ -0,0 +1,101 @@
+use crate::engine::{LineClear, Spin};
+
+/// Guideline scoring, tallied by source for the result screen.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Score {
+    pub total: u64,
+    /// Source, times scored and points, in the order first scored.
+    pub tally: Vec<(&'static str, usize, u64)>,
+}
+
+impl Score {
+    /// Scores `clear` made at `level`, all of it times `multiplier`.
+    /// Returns the points scored.
+    pub fn clear(&mut self, clear: &LineClear, level: u8, multiplier: f64) -> u64 {
+        let Some((name, base)) = base_points(clear) else {
+            return 0;
+        };
+        let level = level as u64;
+        let mut points = vec![(name, base * level)];
+        if clear.back_to_back {
+            points.push(("Back-to-back", base * level / 2));
+        }
+        if clear.lines > 0 && clear.combo > 0 {
+            points.push(("Combo", 50 * clear.combo as u64 * level));
+        }
+        if clear.perfect_clear {
+            let bonus = match (clear.lines.min(4), clear.back_to_back) {
+                (4, true) => 3200,
+                (lines, _) => [800, 1200, 1800, 2000][lines - 1],
+            };
+            points.push(("Perfect clear", bonus * level));
+        }
+        let sum = points.iter().map(|(_, points)| points).sum::<u64>();
+        points.push(("Multiplier", (sum as f64 * (multiplier - 1.0)).round() as u64));
+
+        for &(source, points) in &points {
+            self.add(source, points);
+        }
+        points.iter().map(|(_, points)| points).sum()
+    }
+
+    fn add(&mut self, source: &'static str, points: u64) {
+        if points == 0 {
+            return;
+        }
+        self.total += points;
+        match self.tally.iter_mut().find(|(known, ..)| *known == source) {
+            Some((_, count, total)) => {
+                *count += 1;
+                *total += points;
+            },
+            None => self.tally.push((source, 1, points)),
+        }
+    }
+
+    /// A `source  count  points` line per source.
+    pub fn breakdown(&self) -> Vec<String> {
+        self.tally.iter()
+            .map(|(source, count, points)| format!("{source:<18} {count:>4} {points:>9}"))
+            .collect()
+    }
+}
+
+/// Name and level 1 points of the clear itself, `None` when it scores nothing.
+fn base_points(clear: &LineClear) -> Option<(&'static str, u64)> {
+    let points = match (clear.spin, clear.lines.min(4)) {
+        (None, 0) => return None,
+        (None, 1) => ("Single", 100),
+        (None, 2) => ("Double", 300),
+        (None, 3) => ("Triple", 500),
+        (None, _) => ("Tetris", 800),
+        (Some(Spin::Mini), 0) => ("T-Spin Mini", 100),
+        (Some(Spin::Mini), 1) => ("T-Spin Mini Single", 200),
+        (Some(Spin::Mini), _) => ("T-Spin Mini Double", 400),
+        (Some(Spin::Full), 0) => ("T-Spin", 400),
+        (Some(Spin::Full), 1) => ("T-Spin Single", 800),
+        (Some(Spin::Full), 2) => ("T-Spin Double", 1200),
+        (Some(Spin::Full), _) => ("T-Spin Triple", 1600),
+    };
+    Some(points)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn guideline_points() {
+        let mut score = Score::default();
+        let clear = |lines, spin| LineClear { lines, spin, ..LineClear::default() };
+
+        assert_eq!(score.clear(&clear(0, None), 3, 1.0), 0);
+        assert_eq!(score.clear(&clear(1, None), 2, 1.0), 200);
+        assert_eq!(score.clear(&LineClear { back_to_back: true, combo: 2, ..clear(2, Some(Spin::Full)) }, 1, 1.0), 1900);
+        assert_eq!(score.clear(&LineClear { perfect_clear: true, ..clear(4, None) }, 1, 1.5), 4200);
+        assert_eq!(score.total, 6300);
+        assert_eq!(score.tally[0], ("Single", 1, 200));
+        assert_eq!(score.tally.last(), Some(&("Multiplier", 1, 1400)));
+    }
+}
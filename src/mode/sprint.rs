@@ -0,0 +1,116 @@
+use std::path::PathBuf;
+use crate::engine::{Engine, LineClear};
+use super::{Mode, Stats, data_dir, format_time, replay::Replay};
+
+/// Clear 40 lines as fast as possible.
+pub struct Sprint {
+    pub goal: usize,
+    /// Game time at every tenth line.
+    splits: Vec<std::time::Duration>,
+    best: Option<Replay>,
+}
+
+impl Sprint {
+    pub const GOAL: usize = 40;
+    const SPLIT: usize = 10;
+
+    pub fn new() -> Self {
+        Sprint {
+            goal: Self::GOAL,
+            splits: Vec::new(),
+            best: Replay::load(Self::record_path()).ok(),
+        }
+    }
+
+    /// Replay of the personal best.
+    pub fn record_path() -> PathBuf {
+        data_dir().join("sprint.replay")
+    }
+}
+
+impl Default for Sprint {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Mode for Sprint {
+    fn name(&self) -> &'static str {
+        "sprint"
+    }
+
+    fn locked(&mut self, _engine: &mut Engine, stats: &Stats, _clear: &LineClear) {
+        while stats.lines >= (self.splits.len() + 1) * Self::SPLIT && self.splits.len() * Self::SPLIT < self.goal {
+            self.splits.push(stats.time);
+        }
+    }
+
+    fn is_over(&self, stats: &Stats) -> bool {
+        stats.lines >= self.goal
+    }
+
+    fn panel(&self, stats: &Stats) -> Vec<String> {
+        let mut panel = vec![
+            format!("TIME {}", format_time(stats.time)),
+            format!("LINES {}/{}", stats.lines.min(self.goal), self.goal),
+            format!("PPS {:.2}", stats.pps()),
+        ];
+        for (i, split) in self.splits.iter().enumerate() {
+            panel.push(format!("{:>2} {}", (i + 1) * Self::SPLIT, format_time(*split)));
+        }
+        if let Some(best) = &self.best {
+            panel.push(format!("BEST {}", format_time(best.time)));
+        }
+        panel
+    }
+
+    fn finish(&mut self, stats: &Stats, replay: &Replay) -> Vec<String> {
+        if stats.lines < self.goal {
+            return vec![format!("Topped out at {} lines", stats.lines)];
+        }
+
+        let mut result = vec![
+            format!("{} lines in {}", self.goal, format_time(stats.time)),
+            format!("{:.2} pieces per second, {} finesse faults", stats.pps(), stats.finesse_faults),
+        ];
+        if self.best.as_ref().is_none_or(|best| replay.time < best.time) {
+            match replay.save(Self::record_path()) {
+                Ok(()) => result.push("New personal best!".to_owned()),
+                Err(error) => result.push(format!("Fail to save personal best: {error}")),
+            }
+            self.best = Some(replay.clone());
+        }
+        result
+    }
+
+    fn retry(&self) -> Option<Box<dyn Mode>> {
+        Some(Box::new(Sprint { goal: self.goal, ..Sprint::new() }))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+    use crate::mode::{Game, test::{bot_play, seeded}};
+
+    #[test]
+    fn replay_reproduces_sprint() {
+        let sprint = || Box::new(Sprint { goal: 10, splits: Vec::new(), best: None });
+        let mut game = seeded(21, sprint());
+        bot_play(&mut game, usize::MAX, Duration::from_millis(40));
+        assert_eq!(game.stats.lines, 10);
+
+        let replay = game.replay.to_string().parse::<Replay>().unwrap();
+        assert_eq!(replay, game.replay);
+
+        let mut replayed = Game::new(Engine::with_seed(replay.seed), replay.seed, sprint());
+        for (time, play) in replay.plays {
+            replayed.play(time, play);
+        }
+        assert_eq!(replayed.stats, game.stats);
+        assert_eq!(replayed.engine.matrix(), game.engine.matrix());
+        assert_eq!(replayed.mode.panel(&replayed.stats), game.mode.panel(&game.stats));
+    }
+}
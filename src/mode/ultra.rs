@@ -0,0 +1,114 @@
+use std::time::Duration;
+use crate::engine::{Engine, LineClear};
+use super::{Mode, Stats, format_time, replay::Replay, score::Score};
+
+/// Score as much as possible before the clock runs out.
+pub struct Ultra {
+    pub duration: Duration,
+    /// Scales every clear by `1 + combo / 4`, up to `MAX_MULTIPLIER`.
+    pub combo_multiplier: bool,
+    score: Score,
+    level: u8,
+    multiplier: f64,
+}
+
+impl Ultra {
+    pub const DURATION: Duration = Duration::from_secs(120);
+    const MAX_LEVEL: u8 = 15;
+    const MAX_MULTIPLIER: f64 = 3.0;
+
+    pub fn new(duration: Duration, combo_multiplier: bool) -> Self {
+        Ultra { duration, combo_multiplier, score: Score::default(), level: 1, multiplier: 1.0 }
+    }
+
+    /// Blitz style ramp, getting to level `n` takes `n² - 1` lines.
+    fn level_for(lines: usize) -> u8 {
+        let level = (1..).take_while(|level: &usize| level * level <= lines + 1).last().unwrap_or(1);
+        level.min(Self::MAX_LEVEL as usize) as u8
+    }
+}
+
+impl Default for Ultra {
+    fn default() -> Self {
+        Self::new(Self::DURATION, false)
+    }
+}
+
+impl Mode for Ultra {
+    fn name(&self) -> &'static str {
+        "ultra"
+    }
+
+    fn start(&mut self, engine: &mut Engine) {
+        engine.set_time_limit(Some(self.duration));
+        engine.set_level(self.level);
+    }
+
+    fn locked(&mut self, engine: &mut Engine, stats: &Stats, clear: &LineClear) {
+        self.multiplier = match (self.combo_multiplier, clear.lines) {
+            (false, _) | (_, 0) => 1.0,
+            (true, _) => (1.0 + clear.combo as f64 / 4.0).min(Self::MAX_MULTIPLIER),
+        };
+        self.score.clear(clear, self.level, self.multiplier);
+        self.level = Self::level_for(stats.lines);
+        engine.set_level(self.level);
+    }
+
+    fn is_over(&self, _stats: &Stats) -> bool {
+        false
+    }
+
+    fn panel(&self, stats: &Stats) -> Vec<String> {
+        let mut panel = vec![
+            format!("TIME {}", format_time(self.duration.saturating_sub(stats.time))),
+            format!("SCORE {}", self.score.total),
+            format!("LEVEL {}", self.level),
+            format!("LINES {}", stats.lines),
+        ];
+        if self.combo_multiplier {
+            panel.push(format!("COMBO X{:.2}", self.multiplier));
+        }
+        if stats.time >= self.duration {
+            panel.push("TIME UP".to_owned());
+        }
+        panel
+    }
+
+    fn finish(&mut self, stats: &Stats, _replay: &Replay) -> Vec<String> {
+        let mut result = match stats.time >= self.duration {
+            true => vec![format!("{} points in {}", self.score.total, format_time(self.duration))],
+            false => vec![format!("Topped out with {} points at {}", self.score.total, format_time(stats.time))],
+        };
+        result.push(format!("Level {}, {} lines, {:.2} pieces per second", self.level, stats.lines, stats.pps()));
+        result.extend(self.score.breakdown());
+        result
+    }
+
+    fn retry(&self) -> Option<Box<dyn Mode>> {
+        Some(Box::new(Ultra::new(self.duration, self.combo_multiplier)))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mode::{replay::Play, test::{bot_play, seeded}};
+
+    #[test]
+    fn clock_ends_game() {
+        assert_eq!([0, 2, 3, 7, 8, 300].map(Ultra::level_for), [1, 1, 2, 2, 3, 15]);
+
+        let ultra = Ultra::new(Duration::from_secs(10), true);
+        let mut game = seeded(4, Box::new(ultra));
+        let time = bot_play(&mut game, usize::MAX, Duration::from_millis(20));
+        assert!(!game.engine.is_topped_out());
+        assert_eq!(game.stats.time, Duration::from_secs(10));
+        assert!(game.stats.lines > 0);
+        assert_eq!(game.play(time, Play::HardDrop), None);
+
+        let result = game.finish().unwrap();
+        assert!(result[0].ends_with("in 0:10.000"));
+        assert!(result.iter().any(|line| line.starts_with("Single") || line.starts_with("Double")));
+    }
+}
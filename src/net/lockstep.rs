@@ -0,0 +1,148 @@
+use std::collections::VecDeque;
+use std::io;
+use super::{DesyncCheck, Inputs, Link, Message, Session, Simulation, PLAYERS, protocol_error};
+
+pub const DEFAULT_DELAY: usize = 3;
+
+
+/// Input delay lockstep: local inputs are scheduled `delay` frames ahead and
+/// a frame only runs once the peer's inputs for it arrived.
+pub struct Lockstep<L: Link> {
+    simulation: Simulation,
+    local: usize,
+    delay: usize,
+    /// Known inputs of each player, starting at `simulation.frame`.
+    inputs: [VecDeque<Inputs>; PLAYERS],
+    desync: DesyncCheck,
+    link: L,
+}
+
+impl<L: Link> Lockstep<L> {
+    pub fn new(link: L, seed: u64, delay: usize, local: usize) -> Self {
+        let idle = VecDeque::from(vec![Inputs::default(); delay]);
+        Lockstep {
+            simulation: Simulation::new(seed),
+            local,
+            delay,
+            inputs: [idle.clone(), idle],
+            desync: DesyncCheck::default(),
+            link,
+        }
+    }
+
+    fn remote(&self) -> usize {
+        1 - self.local
+    }
+
+    fn handle(&mut self, message: Message) -> io::Result<()> {
+        match message {
+            Message::Input { frame, keys } => {
+                let remote = self.remote();
+                if frame != self.simulation.frame + self.inputs[remote].len() as u64 {
+                    return Err(protocol_error(format!("input for frame {frame} out of order")));
+                }
+                self.inputs[remote].push_back(keys);
+            },
+            Message::Hash { frame, hash } => self.desync.remote(frame, hash),
+        }
+        Ok(())
+    }
+}
+
+impl<L: Link> Session for Lockstep<L> {
+    fn poll(&mut self, local: Inputs) -> io::Result<bool> {
+        while let Some(message) = self.link.try_receive()? {
+            self.handle(Message::parse(&message)?)?;
+        }
+        // The local input goes out before waiting on the peer, or two peers
+        // without delay would each wait for the other to send first.
+        if self.inputs[self.local].len() <= self.delay {
+            let frame = self.simulation.frame + self.inputs[self.local].len() as u64;
+            self.inputs[self.local].push_back(local);
+            self.link.send(Message::Input { frame, keys: local }.to_json())?;
+        }
+        if self.inputs[self.remote()].is_empty() {
+            return self.desync.check().map(|_| false);
+        }
+
+        let inputs = [0, 1].map(|index| self.inputs[index].pop_front().unwrap());
+        self.simulation.step(inputs);
+
+        if let Some(message) = self.desync.record(&self.simulation) {
+            self.link.send(message.to_json())?;
+        }
+        self.desync.check()?;
+        Ok(true)
+    }
+
+    fn simulation(&self) -> &Simulation {
+        &self.simulation
+    }
+
+    fn local(&self) -> usize {
+        self.local
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::TcpListener;
+    use std::sync::{Arc, Barrier};
+    use std::thread;
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+    use crate::net::{self, Mode};
+
+    fn random_keys(rng: &mut StdRng) -> Inputs {
+        let mut keys = Inputs(rng.gen::<u8>() & 0x77);
+        if rng.gen_ratio(1, 20) {
+            keys.insert(Inputs::HARD_DROP);
+        }
+        keys
+    }
+
+    /// Mashes random keys for `frames` frames, returns the final fingerprint.
+    fn mash(mut session: Box<dyn Session>, frames: u64, barrier: Arc<Barrier>) -> u64 {
+        let mut rng = StdRng::seed_from_u64(session.local() as u64);
+        let mut keys = random_keys(&mut rng);
+        while session.simulation().frame < frames {
+            if session.poll(keys).expect("lockstep failed") {
+                keys = random_keys(&mut rng);
+            } else {
+                thread::yield_now();
+            }
+        }
+        barrier.wait();
+        session.simulation().hash()
+    }
+
+    /// Runs a host and a guest over loopback, returns both fingerprints.
+    fn loopback(delay: usize) -> (u64, u64) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let barrier = Arc::new(Barrier::new(2));
+
+        let host = {
+            let barrier = barrier.clone();
+            thread::spawn(move || {
+                let session = net::host(&listener, 5, Mode::Lockstep(delay)).unwrap();
+                mash(session, 300, barrier)
+            })
+        };
+        let guest = mash(net::join(address).unwrap(), 300, barrier);
+        (host.join().unwrap(), guest)
+    }
+
+    #[test]
+    fn loopback_peers_agree() {
+        let (host, guest) = loopback(DEFAULT_DELAY);
+        assert_eq!(host, guest);
+    }
+
+    #[test]
+    fn runs_without_delay() {
+        let (host, guest) = loopback(0);
+        assert_eq!(host, guest);
+    }
+}
@@ -0,0 +1,333 @@
+//! Online versus: both sides run the same deterministic `Simulation` from
+//! the same seed and only exchange inputs, newline separated JSON over TCP.
+//! `lockstep` waits for the peer's inputs, `rollback` predicts them.
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::{hash::{DefaultHasher, Hash, Hasher}, thread, time::Duration};
+use crate::engine::{MoveKind, piece::RotateKind};
+use crate::json::Json;
+use crate::versus::{Settings, Versus};
+use self::{lockstep::Lockstep, rollback::Rollback};
+
+pub mod lockstep;
+pub mod rollback;
+
+/// Length of one simulation step.
+pub const FRAME: Duration = Duration::from_micros(16_667);
+pub const PLAYERS: usize = 2;
+/// Frames between two desync checks.
+const HASH_INTERVAL: u64 = 60;
+
+
+/// Keys pressed during one frame, one bit each.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Inputs(pub u8);
+
+impl Inputs {
+    pub const LEFT: Self = Inputs(1);
+    pub const RIGHT: Self = Inputs(1 << 1);
+    pub const SOFT_DROP: Self = Inputs(1 << 2);
+    pub const HARD_DROP: Self = Inputs(1 << 3);
+    pub const CLOCKWISE: Self = Inputs(1 << 4);
+    pub const COUNTER_CLOCKWISE: Self = Inputs(1 << 5);
+    pub const HOLD: Self = Inputs(1 << 6);
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+}
+
+
+/// A two player `Versus` advanced in fixed frames, so the same inputs
+/// always lead to the same game.
+#[derive(Clone)]
+pub struct Simulation {
+    pub versus: Versus,
+    /// Time since each cursor last fell a row.
+    gravity: [Duration; PLAYERS],
+    pub frame: u64,
+}
+
+impl Simulation {
+    pub fn new(seed: u64) -> Self {
+        let mut versus = Versus::new(PLAYERS, seed, Settings::default());
+        for player in &mut versus.players {
+            let _ = player.engine.spawn();
+        }
+        Simulation { versus, gravity: [Duration::ZERO; PLAYERS], frame: 0 }
+    }
+
+    /// Applies one frame of inputs for every player, then gravity and garbage.
+    pub fn step(&mut self, inputs: [Inputs; PLAYERS]) {
+        for (index, inputs) in inputs.into_iter().enumerate() {
+            if self.versus.is_over() {
+                break;
+            }
+            let engine = &mut self.versus.players[index].engine;
+            if engine.is_topped_out() || engine.cursor().is_none() {
+                continue;
+            }
+
+            if inputs.contains(Inputs::HOLD) {
+                let _ = engine.hold();
+            }
+            if inputs.contains(Inputs::CLOCKWISE) {
+                let _ = engine.rotate_cursor(RotateKind::Clockwise);
+            }
+            if inputs.contains(Inputs::COUNTER_CLOCKWISE) {
+                let _ = engine.rotate_cursor(RotateKind::CounterClockwise);
+            }
+            if inputs.contains(Inputs::LEFT) {
+                let _ = engine.move_cursor(MoveKind::Left);
+            }
+            if inputs.contains(Inputs::RIGHT) {
+                let _ = engine.move_cursor(MoveKind::Right);
+            }
+            if inputs.contains(Inputs::SOFT_DROP) && engine.soft_drop() {
+                self.gravity[index] = Duration::ZERO;
+            }
+
+            let mut locked = false;
+            self.gravity[index] += FRAME;
+            if inputs.contains(Inputs::HARD_DROP) && engine.cursor().is_some() {
+                engine.hard_drop();
+                locked = true;
+            } else if self.gravity[index] >= engine.drop_time() {
+                if engine.cursor_hit_down() {
+                    engine.hard_drop();
+                    locked = true;
+                } else {
+                    engine.soft_drop();
+                }
+                self.gravity[index] = Duration::ZERO;
+            }
+
+            if locked {
+                self.gravity[index] = Duration::ZERO;
+                self.versus.line_clear(index);
+                let engine = &mut self.versus.players[index].engine;
+                if engine.cursor().is_none() && !engine.is_topped_out() {
+                    let _ = engine.spawn();
+                }
+            }
+        }
+
+        self.versus.update(FRAME);
+        self.frame += 1;
+    }
+
+    /// Fingerprint of every matrix and cursor, compared between peers to catch desyncs.
+    pub fn hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.frame.hash(&mut hasher);
+        for player in &self.versus.players {
+            player.engine.matrix().hash(&mut hasher);
+            player.engine.cursor().hash(&mut hasher);
+            player.engine.held().hash(&mut hasher);
+            player.pending().hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+}
+
+
+/// An online game as the window sees it.
+pub trait Session {
+    /// Runs the next frame with `local` pressed. Returns false when it has to
+    /// wait on the peer, so the caller keeps the inputs for the next try.
+    /// Fails on disconnects and desyncs.
+    fn poll(&mut self, local: Inputs) -> io::Result<bool>;
+    /// The game to show, possibly ahead of what the peer confirmed.
+    fn simulation(&self) -> &Simulation;
+    /// Our player index, the host plays 0.
+    fn local(&self) -> usize;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    /// Frames of input delay.
+    Lockstep(usize),
+    Rollback,
+}
+
+/// Waits for a player to connect and tells them the seed and netcode.
+pub fn host(listener: &TcpListener, seed: u64, mode: Mode) -> io::Result<Box<dyn Session>> {
+    let (stream, _) = listener.accept()?;
+    let mut connection = Connection::new(stream)?;
+    let (name, delay) = match mode {
+        Mode::Lockstep(delay) => ("lockstep", delay),
+        Mode::Rollback => ("rollback", 0),
+    };
+    connection.send(Json::object([
+        ("type", "hello".into()),
+        ("seed", seed.to_string().into()),
+        ("netcode", name.into()),
+        ("delay", (delay as i64).into()),
+    ]))?;
+    Ok(session(connection, seed, mode, 0))
+}
+
+/// Joins a hosted game, using whatever netcode the host picked.
+pub fn join(address: impl ToSocketAddrs) -> io::Result<Box<dyn Session>> {
+    let mut connection = Connection::new(TcpStream::connect(address)?)?;
+    let hello = connection.receive()?;
+    if hello.get("type").and_then(Json::as_str) != Some("hello") {
+        return Err(protocol_error(format!("expected hello, got {hello}")));
+    }
+    let seed = hello.get("seed").and_then(Json::as_str).and_then(|seed| seed.parse().ok());
+    let delay = hello.get("delay").and_then(Json::as_i64).and_then(|delay| usize::try_from(delay).ok());
+    let mode = match hello.get("netcode").and_then(Json::as_str) {
+        Some("lockstep") => delay.map(Mode::Lockstep),
+        Some("rollback") => Some(Mode::Rollback),
+        _ => None,
+    };
+    let (Some(seed), Some(mode)) = (seed, mode) else {
+        return Err(protocol_error(format!("bad hello {hello}")));
+    };
+    Ok(session(connection, seed, mode, 1))
+}
+
+fn session(connection: Connection, seed: u64, mode: Mode, local: usize) -> Box<dyn Session> {
+    match mode {
+        Mode::Lockstep(delay) => Box::new(Lockstep::new(connection, seed, delay, local)),
+        Mode::Rollback => Box::new(Rollback::new(connection, seed, local)),
+    }
+}
+
+
+/// Where sessions send and receive their messages, TCP outside of tests.
+pub trait Link {
+    fn send(&mut self, message: Json) -> io::Result<()>;
+    /// Next message if one arrived, never blocks.
+    fn try_receive(&mut self) -> io::Result<Option<Json>>;
+}
+
+fn protocol_error(error: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+/// A TCP stream read on its own thread, so polling never blocks the game.
+pub struct Connection {
+    stream: TcpStream,
+    incoming: Receiver<io::Result<Json>>,
+}
+
+impl Connection {
+    pub fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nodelay(true)?;
+        let reader = BufReader::new(stream.try_clone()?);
+        let (sender, incoming) = mpsc::channel();
+
+        thread::spawn(move || {
+            for line in reader.lines() {
+                let message = line.and_then(|line| Json::parse(&line).map_err(protocol_error));
+                if sender.send(message).is_err() {
+                    return;
+                }
+            }
+            let _ = sender.send(Err(io::Error::new(io::ErrorKind::ConnectionAborted, "peer disconnected")));
+        });
+        Ok(Connection { stream, incoming })
+    }
+
+    fn receive(&mut self) -> io::Result<Json> {
+        self.incoming.recv().map_err(|_| protocol_error("connection closed"))?
+    }
+}
+
+impl Link for Connection {
+    fn send(&mut self, message: Json) -> io::Result<()> {
+        writeln!(self.stream, "{message}")
+    }
+
+    fn try_receive(&mut self) -> io::Result<Option<Json>> {
+        match self.incoming.try_recv() {
+            Ok(message) => message.map(Some),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err(protocol_error("connection closed")),
+        }
+    }
+}
+
+
+/// Messages both netcodes understand.
+enum Message {
+    Input { frame: u64, keys: Inputs },
+    Hash { frame: u64, hash: u64 },
+}
+
+impl Message {
+    fn parse(message: &Json) -> io::Result<Self> {
+        let frame = message.get("frame").and_then(Json::as_i64).map(|frame| frame as u64);
+        let parsed = match (message.get("type").and_then(Json::as_str), frame) {
+            (Some("input"), Some(frame)) => message.get("keys")
+                .and_then(Json::as_i64)
+                .and_then(|keys| u8::try_from(keys).ok())
+                .map(|keys| Message::Input { frame, keys: Inputs(keys) }),
+            (Some("hash"), Some(frame)) => message.get("hash")
+                .and_then(Json::as_str)
+                .and_then(|hash| hash.parse().ok())
+                .map(|hash| Message::Hash { frame, hash }),
+            _ => None,
+        };
+        parsed.ok_or_else(|| protocol_error(format!("unknown message {message}")))
+    }
+
+    fn to_json(&self) -> Json {
+        match *self {
+            Message::Input { frame, keys } => Json::object([
+                ("type", "input".into()),
+                ("frame", (frame as i64).into()),
+                ("keys", i64::from(keys.0).into()),
+            ]),
+            // hashes don't fit in a JSON number
+            Message::Hash { frame, hash } => Json::object([
+                ("type", "hash".into()),
+                ("frame", (frame as i64).into()),
+                ("hash", hash.to_string().into()),
+            ]),
+        }
+    }
+}
+
+
+/// Compares our fingerprints of confirmed frames with the peer's.
+#[derive(Default)]
+struct DesyncCheck {
+    local: VecDeque<(u64, u64)>,
+    remote: VecDeque<(u64, u64)>,
+}
+
+impl DesyncCheck {
+    /// Records `simulation` every `HASH_INTERVAL` frames, returning the message for the peer.
+    fn record(&mut self, simulation: &Simulation) -> Option<Message> {
+        if !simulation.frame.is_multiple_of(HASH_INTERVAL) {
+            return None;
+        }
+        let hash = simulation.hash();
+        self.local.push_back((simulation.frame, hash));
+        Some(Message::Hash { frame: simulation.frame, hash })
+    }
+
+    fn remote(&mut self, frame: u64, hash: u64) {
+        self.remote.push_back((frame, hash));
+    }
+
+    fn check(&mut self) -> io::Result<()> {
+        while let (Some(&local), Some(&remote)) = (self.local.front(), self.remote.front()) {
+            if local != remote {
+                return Err(protocol_error(format!("desync at frame {}", local.0)));
+            }
+            self.local.pop_front();
+            self.remote.pop_front();
+        }
+        Ok(())
+    }
+}
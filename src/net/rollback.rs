@@ -0,0 +1,218 @@
+use std::collections::VecDeque;
+use std::io;
+use super::{DesyncCheck, Inputs, Link, Message, Session, Simulation, PLAYERS, protocol_error};
+
+/// Frames the prediction may run ahead of the confirmed state before waiting on the peer.
+pub const MAX_ROLLBACK: usize = 8;
+
+
+/// Rollback netcode: local inputs apply at once, the peer's are guessed to
+/// repeat their last one, and when a guess turns out wrong the game is
+/// restored to the last confirmed frame and run forward again.
+pub struct Rollback<L: Link> {
+    /// State after every frame both players' inputs are known for.
+    confirmed: Simulation,
+    /// `confirmed` run ahead on guessed peer inputs, the one shown.
+    predicted: Simulation,
+    local: usize,
+    /// Our inputs from `confirmed.frame` on.
+    local_inputs: VecDeque<Inputs>,
+    /// The peer's inputs from `confirmed.frame` on.
+    remote_inputs: VecDeque<Inputs>,
+    /// Peer inputs `predicted` was run with, from `confirmed.frame` on.
+    guesses: VecDeque<Inputs>,
+    last_remote: Inputs,
+    mispredicted: bool,
+    /// How many times the prediction was thrown away.
+    pub rollbacks: usize,
+    desync: DesyncCheck,
+    link: L,
+}
+
+impl<L: Link> Rollback<L> {
+    pub fn new(link: L, seed: u64, local: usize) -> Self {
+        let simulation = Simulation::new(seed);
+        Rollback {
+            confirmed: simulation.clone(),
+            predicted: simulation,
+            local,
+            local_inputs: VecDeque::new(),
+            remote_inputs: VecDeque::new(),
+            guesses: VecDeque::new(),
+            last_remote: Inputs::default(),
+            mispredicted: false,
+            rollbacks: 0,
+            desync: DesyncCheck::default(),
+            link,
+        }
+    }
+
+    fn ordered(&self, local: Inputs, remote: Inputs) -> [Inputs; PLAYERS] {
+        match self.local {
+            0 => [local, remote],
+            _ => [remote, local],
+        }
+    }
+
+    /// Takes in the peer's messages and confirms every frame they complete.
+    fn receive(&mut self) -> io::Result<()> {
+        while let Some(message) = self.link.try_receive()? {
+            match Message::parse(&message)? {
+                Message::Input { frame, keys } => {
+                    let index = self.remote_inputs.len();
+                    if frame != self.confirmed.frame + index as u64 {
+                        return Err(protocol_error(format!("input for frame {frame} out of order")));
+                    }
+                    self.mispredicted |= self.guesses.get(index).is_some_and(|guess| *guess != keys);
+                    self.remote_inputs.push_back(keys);
+                    self.last_remote = keys;
+                },
+                Message::Hash { frame, hash } => self.desync.remote(frame, hash),
+            }
+        }
+        self.confirm()?;
+        self.desync.check()
+    }
+
+    fn confirm(&mut self) -> io::Result<()> {
+        while !self.local_inputs.is_empty() && !self.remote_inputs.is_empty() {
+            let local = self.local_inputs.pop_front().unwrap();
+            let remote = self.remote_inputs.pop_front().unwrap();
+            self.guesses.pop_front();
+            self.confirmed.step(self.ordered(local, remote));
+
+            if let Some(message) = self.desync.record(&self.confirmed) {
+                self.link.send(message.to_json())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Brings `predicted` level with our inputs, restarting from `confirmed` after a bad guess.
+    fn predict(&mut self) {
+        if std::mem::take(&mut self.mispredicted) {
+            self.predicted = self.confirmed.clone();
+            self.guesses.clear();
+            self.rollbacks += 1;
+        }
+        while self.guesses.len() < self.local_inputs.len() {
+            let index = self.guesses.len();
+            let remote = self.remote_inputs.get(index).copied().unwrap_or(self.last_remote);
+            self.predicted.step(self.ordered(self.local_inputs[index], remote));
+            self.guesses.push_back(remote);
+        }
+    }
+}
+
+impl<L: Link> Session for Rollback<L> {
+    fn poll(&mut self, local: Inputs) -> io::Result<bool> {
+        self.receive()?;
+        if self.local_inputs.len() >= MAX_ROLLBACK {
+            return Ok(false);
+        }
+
+        let frame = self.confirmed.frame + self.local_inputs.len() as u64;
+        self.local_inputs.push_back(local);
+        self.link.send(Message::Input { frame, keys: local }.to_json())?;
+
+        self.confirm()?;
+        self.predict();
+        Ok(true)
+    }
+
+    fn simulation(&self) -> &Simulation {
+        &self.predicted
+    }
+
+    fn local(&self) -> usize {
+        self.local
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{cell::{Cell, RefCell}, rc::Rc};
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+    use crate::json::Json;
+
+    type Queue = Rc<RefCell<VecDeque<(u64, Json)>>>;
+
+    /// In memory link delivering messages in order after a random latency,
+    /// counted in frames of a shared clock.
+    struct Wire {
+        clock: Rc<Cell<u64>>,
+        outgoing: Queue,
+        incoming: Queue,
+        rng: StdRng,
+        latency: u64,
+        jitter: u64,
+        last_delivery: u64,
+    }
+
+    impl Link for Wire {
+        fn send(&mut self, message: Json) -> io::Result<()> {
+            let delay = self.latency + self.rng.gen_range(0..=self.jitter);
+            self.last_delivery = self.last_delivery.max(self.clock.get() + delay);
+            self.outgoing.borrow_mut().push_back((self.last_delivery, message));
+            Ok(())
+        }
+
+        fn try_receive(&mut self) -> io::Result<Option<Json>> {
+            let mut incoming = self.incoming.borrow_mut();
+            match incoming.front() {
+                Some((delivery, _)) if *delivery <= self.clock.get() => Ok(incoming.pop_front().map(|(_, message)| message)),
+                _ => Ok(None),
+            }
+        }
+    }
+
+    fn wires(clock: &Rc<Cell<u64>>, latency: u64, jitter: u64) -> [Wire; 2] {
+        let queues = [Queue::default(), Queue::default()];
+        [0, 1].map(|side| Wire {
+            clock: clock.clone(),
+            outgoing: queues[side].clone(),
+            incoming: queues[1 - side].clone(),
+            rng: StdRng::seed_from_u64(side as u64),
+            latency,
+            jitter,
+            last_delivery: 0,
+        })
+    }
+
+    #[test]
+    fn laggy_peers_converge() {
+        const FRAMES: u64 = 600;
+        let clock = Rc::new(Cell::new(0));
+        let mut peers = wires(&clock, 3, 4).into_iter()
+            .enumerate()
+            .map(|(local, wire)| Rollback::new(wire, 9, local))
+            .collect::<Vec<_>>();
+        let mut rng = StdRng::seed_from_u64(2);
+        let mut keys = [Inputs::default(); 2];
+
+        while clock.get() < FRAMES * 2 {
+            for (peer, keys) in peers.iter_mut().zip(&mut keys) {
+                if peer.predicted.frame >= FRAMES {
+                    peer.receive().unwrap();
+                    continue;
+                }
+                if peer.poll(*keys).unwrap() {
+                    *keys = Inputs(rng.gen::<u8>() & 0x77);
+                    if rng.gen_ratio(1, 20) {
+                        keys.insert(Inputs::HARD_DROP);
+                    }
+                }
+            }
+            clock.set(clock.get() + 1);
+        }
+
+        for peer in &peers {
+            assert_eq!(peer.confirmed.frame, FRAMES);
+            assert_eq!(peer.predicted.hash(), peer.confirmed.hash());
+        }
+        assert_eq!(peers[0].confirmed.hash(), peers[1].confirmed.hash());
+        assert!(peers.iter().any(|peer| peer.rollbacks > 0), "latency should force rollbacks");
+    }
+}
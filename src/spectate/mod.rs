@@ -0,0 +1,318 @@
+//! Streams games to other processes for casting and debugging bots.
+//!
+//! A `Server` listens on a local TCP port and writes newline separated JSON,
+//! reusing the TBP encodings for boards (40 rows bottom first, cells `null`
+//! or a piece letter, `"G"` for garbage), pieces (`"T"`) and locations
+//! (`{"type", "orientation", "x", "y"}` around the SRS center):
+//!
+//! - `{"type": "snapshot", "players": [player, ...]}` with each player as
+//!   `{"board", "current": location | null, "hold": piece | null, "queue": [piece],
+//!   "pending": garbage lines, "topped_out": bool, "pieces": set file | null}`.
+//!   `pieces` holds the shapes of a custom piece set, which pieces and cells are
+//!   then named after. Sent on connect, whenever a board changes and at least
+//!   once a second.
+//! - `{"type": "move", "player", "current": location | null}` when only a cursor changed.
+//! - `{"type": "clear", "player", "lines", "spin": "mini" | "full" | null, "combo",
+//!   "back_to_back", "perfect_clear"}` after every locked piece.
+//! - `{"type": "game_over", "winner": player | null}`.
+
+use std::io::{self, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex, mpsc::{self, SyncSender}};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::engine::{Engine, LineClear, Matrix, PieceSet, Spin, piece::{Piece, Kind as PieceKind}};
+use crate::json::Json;
+use crate::tbp::{board, kind_name, location, parse_board, parse_kind, parse_location};
+
+pub const DEFAULT_PORT: u16 = 7879;
+const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(1);
+/// Messages waiting for a spectator before it counts as gone.
+const CLIENT_BACKLOG: usize = 256;
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+
+/// What a spectator knows about one player.
+#[derive(Clone, Debug, PartialEq)]
+pub struct View {
+    pub matrix: Matrix,
+    pub cursor: Option<Piece>,
+    pub hold: Option<PieceKind>,
+    pub queue: Vec<PieceKind>,
+    pub pending: usize,
+    pub topped_out: bool,
+    pub piece_set: PieceSet,
+}
+
+impl View {
+    pub fn of(engine: &Engine, pending: usize) -> Self {
+        View {
+            matrix: *engine.matrix(),
+            cursor: engine.cursor(),
+            hold: engine.held(),
+            queue: engine.queue().collect(),
+            pending,
+            topped_out: engine.is_topped_out(),
+            piece_set: engine.piece_set().clone(),
+        }
+    }
+
+    fn to_json(&self) -> Json {
+        let pieces = self.piece_set.to_string();
+        Json::object([
+            ("board", board(&self.matrix, self.piece_set.kinds())),
+            ("current", self.cursor.as_ref().map(location).into()),
+            ("hold", self.hold.map(kind_name).into()),
+            ("queue", self.queue.iter().copied().map(kind_name).collect()),
+            ("pending", (self.pending as i64).into()),
+            ("topped_out", self.topped_out.into()),
+            ("pieces", (!pieces.is_empty()).then_some(pieces.as_str()).into()),
+        ])
+    }
+
+    fn parse(json: &Json) -> Option<Self> {
+        let piece_set = match json.get("pieces") {
+            Some(Json::Null) | None => PieceSet::default(),
+            Some(pieces) => pieces.as_str()?.parse().ok()?,
+        };
+        let custom = piece_set.kinds();
+        Some(View {
+            matrix: parse_board(json.get("board")?, custom)?,
+            cursor: parse_cursor(json.get("current")?, custom)?,
+            hold: match json.get("hold")? {
+                Json::Null => None,
+                hold => Some(parse_kind(hold, custom)?),
+            },
+            queue: json.get("queue")?.as_array()?.iter().map(|kind| parse_kind(kind, custom)).collect::<Option<_>>()?,
+            pending: json.get("pending")?.as_i64()? as usize,
+            topped_out: json.get("topped_out")?.as_bool()?,
+            piece_set,
+        })
+    }
+}
+
+/// `Some(None)` for a `null` location, `None` when it doesn't parse.
+fn parse_cursor(json: &Json, custom: &[PieceKind]) -> Option<Option<Piece>> {
+    match json {
+        Json::Null => Some(None),
+        json => parse_location(json, custom).map(Some),
+    }
+}
+
+/// Updates the spectator's `views` with one message from the server.
+/// Returns the winner once the game is over.
+pub fn apply(views: &mut Vec<View>, message: &Json) -> Result<Option<Option<usize>>, String> {
+    let bad = || format!("bad message {message}");
+    let player = message.get("player").and_then(Json::as_i64).map(|player| player as usize);
+
+    match message.get("type").and_then(Json::as_str) {
+        Some("snapshot") => {
+            *views = message.get("players")
+                .and_then(Json::as_array)
+                .and_then(|players| players.iter().map(View::parse).collect())
+                .ok_or_else(bad)?;
+        },
+        Some("move") => {
+            let view = player.and_then(|player| views.get_mut(player)).ok_or_else(bad)?;
+            view.cursor = message.get("current").and_then(|current| parse_cursor(current, view.piece_set.kinds())).ok_or_else(bad)?;
+        },
+        Some("clear") => {},
+        Some("game_over") => {
+            let winner = message.get("winner").ok_or_else(bad)?.as_i64().map(|winner| winner as usize);
+            return Ok(Some(winner));
+        },
+        _ => return Err(bad()),
+    }
+    Ok(None)
+}
+
+
+/// Broadcasts games to every connected spectator.
+pub struct Server {
+    address: SocketAddr,
+    /// Lines queued for each spectator's writer thread.
+    clients: Arc<Mutex<Vec<SyncSender<String>>>>,
+    /// Latest snapshot, greeting spectators as they connect.
+    latest: Arc<Mutex<Json>>,
+    views: Vec<View>,
+    last_snapshot: Instant,
+}
+
+impl Server {
+    pub fn bind(address: impl ToSocketAddrs) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let address = listener.local_addr()?;
+        let clients = Arc::new(Mutex::new(Vec::new()));
+        let latest = Arc::new(Mutex::new(snapshot(&[])));
+
+        let (accepted, greeting) = (clients.clone(), latest.clone());
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let Ok(client) = Self::writer(stream) else {
+                    continue;
+                };
+                // holding the client list keeps broadcasts from slipping in before the greeting
+                let mut clients = accepted.lock().unwrap();
+                if client.try_send(greeting.lock().unwrap().to_string()).is_ok() {
+                    clients.push(client);
+                }
+            }
+        });
+        Ok(Server { address, clients, latest, views: Vec::new(), last_snapshot: Instant::now() })
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Writes the lines sent to it on a thread of its own, so a slow
+    /// spectator never holds up the game.
+    fn writer(mut stream: TcpStream) -> io::Result<SyncSender<String>> {
+        stream.set_nodelay(true)?;
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        let (sender, lines) = mpsc::sync_channel::<String>(CLIENT_BACKLOG);
+        thread::spawn(move || {
+            for line in lines {
+                if writeln!(stream, "{line}").is_err() {
+                    return;
+                }
+            }
+        });
+        Ok(sender)
+    }
+
+    /// Queues `message` for every spectator, dropping those too far behind.
+    fn broadcast(&self, message: &Json) {
+        let line = message.to_string();
+        self.clients.lock().unwrap().retain(|client| client.try_send(line.clone()).is_ok());
+    }
+
+    /// Sends whatever changed in the `players` since the last call, as each
+    /// engine with the garbage lines it has coming.
+    pub fn observe(&mut self, players: &[(&Engine, usize)]) {
+        let views = players.iter().map(|(engine, pending)| View::of(engine, *pending)).collect::<Vec<_>>();
+        let only_cursors = views.len() == self.views.len() && views.iter().zip(&self.views)
+            .all(|(view, seen)| View { cursor: seen.cursor, ..view.clone() } == *seen);
+
+        if !only_cursors || self.last_snapshot.elapsed() >= SNAPSHOT_INTERVAL {
+            let message = snapshot(&views);
+            *self.latest.lock().unwrap() = message.clone();
+            self.broadcast(&message);
+            self.last_snapshot = Instant::now();
+        } else {
+            for (player, (view, seen)) in views.iter().zip(&self.views).enumerate() {
+                if view.cursor != seen.cursor {
+                    self.broadcast(&Json::object([
+                        ("type", "move".into()),
+                        ("player", (player as i64).into()),
+                        ("current", view.cursor.as_ref().map(location).into()),
+                    ]));
+                }
+            }
+        }
+        self.views = views;
+    }
+
+    pub fn clear(&self, player: usize, clear: &LineClear) {
+        let spin = clear.spin.map(|spin| match spin {
+            Spin::Mini => "mini",
+            Spin::Full => "full",
+        });
+        self.broadcast(&Json::object([
+            ("type", "clear".into()),
+            ("player", (player as i64).into()),
+            ("lines", (clear.lines as i64).into()),
+            ("spin", spin.into()),
+            ("combo", (clear.combo as i64).into()),
+            ("back_to_back", clear.back_to_back.into()),
+            ("perfect_clear", clear.perfect_clear.into()),
+        ]));
+    }
+
+    pub fn game_over(&self, winner: Option<usize>) {
+        self.broadcast(&Json::object([
+            ("type", "game_over".into()),
+            ("winner", winner.map(|winner| winner as i64).into()),
+        ]));
+    }
+}
+
+fn snapshot(views: &[View]) -> Json {
+    Json::object([
+        ("type", "snapshot".into()),
+        ("players", views.iter().map(View::to_json).collect()),
+    ])
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use crate::bot::Bot;
+    use crate::engine::MoveKind;
+
+    #[test]
+    fn spectator_follows_game() {
+        let mut server = Server::bind("127.0.0.1:0").unwrap();
+        let mut lines = BufReader::new(TcpStream::connect(server.address()).unwrap()).lines();
+        let mut next_message = || Json::parse(&lines.next().unwrap().unwrap()).unwrap();
+
+        let mut views = Vec::new();
+        assert_eq!(apply(&mut views, &next_message()), Ok(None));
+        assert!(views.is_empty());
+
+        let mut engine = Engine::with_seed(4);
+        let bot = Bot { lookahead: 0, ..Bot::default() };
+        engine.spawn().unwrap();
+        server.observe(&[(&engine, 0)]);
+        engine.move_cursor(MoveKind::Left).unwrap();
+        server.observe(&[(&engine, 0)]);
+        for _ in 0..5 {
+            bot.play_piece(&mut engine).unwrap();
+            server.observe(&[(&engine, 3)]);
+        }
+        server.game_over(Some(0));
+
+        let mut moves = 0;
+        loop {
+            let message = next_message();
+            moves += usize::from(message.get("type").and_then(Json::as_str) == Some("move"));
+            if let Some(winner) = apply(&mut views, &message).unwrap() {
+                assert_eq!(winner, Some(0));
+                break;
+            }
+        }
+        assert_eq!(moves, 1);
+        assert_eq!(views, vec![View::of(&engine, 3)]);
+    }
+
+    #[test]
+    fn drops_stalled_spectators() {
+        let server = Server::bind("127.0.0.1:0").unwrap();
+        let _stalled = TcpStream::connect(server.address()).unwrap();
+        while server.clients.lock().unwrap().is_empty() {
+            thread::yield_now();
+        }
+
+        let message = Json::from("x".repeat(1 << 16));
+        while !server.clients.lock().unwrap().is_empty() {
+            server.broadcast(&message);
+        }
+    }
+
+    #[test]
+    fn custom_piece_sets() {
+        let mut engine = Engine::with_seed(2);
+        engine.set_piece_set(PieceSet::named("pentominoes").unwrap());
+        let bot = Bot { lookahead: 0, ..Bot::default() };
+        for _ in 0..3 {
+            bot.play_piece(&mut engine).unwrap();
+        }
+        engine.spawn().unwrap();
+
+        let mut views = Vec::new();
+        apply(&mut views, &snapshot(&[View::of(&engine, 0)])).unwrap();
+        assert_eq!(views, vec![View::of(&engine, 0)]);
+    }
+}
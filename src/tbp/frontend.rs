@@ -0,0 +1,210 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use crate::bot::placements;
+use crate::engine::{Engine, piece::Piece};
+use crate::json::Json;
+use super::{board, kind_name, message, parse_location, same_cells};
+
+const DEFAULT_PIECES: usize = 1000;
+
+
+/// A bot running as a child process.
+struct Contestant {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Contestant {
+    fn launch(command_line: &str) -> io::Result<Self> {
+        let mut words = command_line.split_whitespace();
+        let program = words.next().ok_or_else(|| protocol_error("empty bot command"))?;
+        let mut child = Command::new(program)
+            .args(words)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        Ok(Contestant { child, stdin, stdout })
+    }
+
+    fn send(&mut self, message: Json) -> io::Result<()> {
+        writeln!(self.stdin, "{message}")?;
+        self.stdin.flush()
+    }
+
+    fn receive(&mut self) -> io::Result<Json> {
+        let mut line = String::new();
+        if self.stdout.read_line(&mut line)? == 0 {
+            return Err(protocol_error("bot closed its output"));
+        }
+        Json::parse(&line).map_err(protocol_error)
+    }
+
+    fn expect(&mut self, kind: &str) -> io::Result<Json> {
+        let message = self.receive()?;
+        match message.get("type").and_then(Json::as_str) {
+            Some(found) if found == kind => Ok(message),
+            Some("error") => Err(protocol_error(format!("bot reported error: {message}"))),
+            _ => Err(protocol_error(format!("expected {kind}, got {message}"))),
+        }
+    }
+}
+
+impl Drop for Contestant {
+    fn drop(&mut self) {
+        let _ = self.send(message("quit"));
+        let _ = self.child.wait();
+    }
+}
+
+fn protocol_error(error: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+
+#[derive(Clone, Debug, Default)]
+pub struct Outcome {
+    pub name: String,
+    pub pieces: usize,
+    pub lines: usize,
+    /// Why the game ended before the piece limit, if it did.
+    pub ended_by: Option<String>,
+}
+
+/// Plays one seeded game against the bot behind `command_line`.
+pub fn play(command_line: &str, seed: u64, pieces: usize) -> io::Result<Outcome> {
+    let mut bot = Contestant::launch(command_line)?;
+
+    let info = bot.expect("info")?;
+    let mut outcome = Outcome {
+        name: info.get("name").and_then(Json::as_str).unwrap_or(command_line).to_owned(),
+        ..Outcome::default()
+    };
+
+    bot.send(message("rules"))?;
+    bot.expect("ready")?;
+
+    let mut engine = Engine::with_seed(seed);
+    engine.spawn().map_err(|_| protocol_error("could not spawn first piece"))?;
+    bot.send(start(&engine))?;
+
+    while outcome.pieces < pieces {
+        bot.send(message("suggest"))?;
+        let suggestion = bot.expect("suggestion")?;
+        let Some(chosen) = suggestion.get("moves").and_then(Json::as_array).and_then(<[_]>::first) else {
+            outcome.ended_by = Some("no move suggested".to_owned());
+            break;
+        };
+        let Some(target) = chosen.get("location").and_then(|location| parse_location(location, &[])) else {
+            outcome.ended_by = Some(format!("unreadable move {chosen}"));
+            break;
+        };
+        let Ok(revealed) = apply(&mut engine, &target) else {
+            outcome.ended_by = Some(format!("illegal move {chosen}"));
+            break;
+        };
+        bot.send(Json::object([("type", "play".into()), ("move", chosen.clone())]))?;
+
+        engine.line_clear(|lines| outcome.lines += lines.len());
+        outcome.pieces += 1;
+        if engine.is_topped_out() || engine.spawn().is_err() {
+            outcome.ended_by = Some("topped out".to_owned());
+            break;
+        }
+
+        let queue = engine.queue().collect::<Vec<_>>();
+        for kind in &queue[queue.len() - revealed..] {
+            bot.send(Json::object([("type", "new_piece".into()), ("piece", kind_name(*kind).into())]))?;
+        }
+    }
+
+    bot.send(message("stop"))?;
+    Ok(outcome)
+}
+
+fn start(engine: &Engine) -> Json {
+    let current = engine.cursor().map(|cursor| cursor.kind);
+    Json::object([
+        ("type", "start".into()),
+        ("hold", engine.held().map(kind_name).into()),
+        ("queue", current.into_iter().chain(engine.queue()).map(kind_name).collect()),
+        ("combo", 0i64.into()),
+        ("back_to_back", false.into()),
+        ("board", board(engine.matrix(), &[])),
+    ])
+}
+
+/// Moves the cursor to `target` through the engine and drops it.
+/// Returns how many pieces were pulled from the queue, which the bot has to hear about.
+fn apply(engine: &mut Engine, target: &Piece) -> Result<usize, ()> {
+    let mut revealed = 1;
+    if engine.cursor().ok_or(())?.kind != target.kind {
+        if engine.held().is_none() {
+            revealed += 1;
+        }
+        engine.hold()?;
+    }
+
+    let cursor = engine.cursor().ok_or(())?;
+    let placement = placements(engine.matrix(), cursor)
+        .into_iter()
+        .find(|placement| same_cells(&placement.piece, target))
+        .ok_or(())?;
+
+    for action in placement.actions {
+        action.apply(engine)?;
+    }
+    engine.hard_drop();
+    Ok(revealed)
+}
+
+/// Seed, piece count and bot commands from `[--seed N] [--pieces N] <bot command>...`
+fn parse_args(args: &[String]) -> (u64, usize, Vec<&str>) {
+    let mut seed = 0;
+    let mut pieces = DEFAULT_PIECES;
+    let mut commands = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--seed" => seed = args.next().and_then(|n| n.parse().ok()).expect("--seed needs a number"),
+            "--pieces" => pieces = args.next().and_then(|n| n.parse().ok()).expect("--pieces needs a number"),
+            command => commands.push(command),
+        }
+    }
+    (seed, pieces, commands)
+}
+
+/// `tetris-tbp [--seed N] [--pieces N] <bot command>...`
+/// Every bot plays the same seeded game, so results compare directly.
+pub fn run(args: &[String]) {
+    let (seed, pieces, commands) = parse_args(args);
+
+    for command in commands {
+        match play(command, seed, pieces) {
+            Ok(outcome) => println!(
+                "{:>24}: {} pieces, {} lines{}",
+                outcome.name,
+                outcome.pieces,
+                outcome.lines,
+                outcome.ended_by.map(|reason| format!(" ({reason})")).unwrap_or_default(),
+            ),
+            Err(error) => println!("{command:>24}: failed: {error}"),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn args_keep_piece_count() {
+        let args = ["--pieces", "5", "./bot --fast"].map(String::from);
+        assert_eq!(parse_args(&args), (0, 5, vec!["./bot --fast"]));
+    }
+}
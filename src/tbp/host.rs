@@ -0,0 +1,101 @@
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Write};
+use crate::bot::Bot;
+use crate::engine::{Matrix, piece::{Piece, Kind as PieceKind}};
+use crate::json::Json;
+use super::{message, move_json, parse_board, parse_kind, parse_location};
+
+
+/// What the frontend has told us about its game.
+struct State {
+    matrix: Matrix,
+    hold: Option<PieceKind>,
+    queue: VecDeque<PieceKind>,
+}
+
+impl State {
+    fn start(json: &Json) -> Option<Self> {
+        Some(State {
+            matrix: parse_board(json.get("board")?, &[])?,
+            hold: json.get("hold").and_then(|hold| parse_kind(hold, &[])),
+            queue: json.get("queue")?.as_array()?.iter().map(|kind| parse_kind(kind, &[])).collect::<Option<_>>()?,
+        })
+    }
+
+    fn suggest(&self, bot: &Bot) -> Vec<Json> {
+        let mut queue = self.queue.iter().copied();
+        let Some(current) = queue.next() else {
+            return Vec::new();
+        };
+        let rest = queue.collect::<Vec<_>>();
+
+        bot.suggest(&self.matrix, Piece::spawned(current), self.hold, &rest, true)
+            .map(|plan| move_json(&plan.target))
+            .into_iter()
+            .collect()
+    }
+
+    fn play(&mut self, piece: &Piece) {
+        if self.queue.front() == Some(&piece.kind) {
+            self.queue.pop_front();
+        } else if let Some(current) = self.queue.pop_front() {
+            // Held: the played piece came from hold, or from the queue if hold was empty.
+            if self.hold.replace(current).is_none() {
+                self.queue.pop_front();
+            }
+        }
+
+        self.matrix.place(piece);
+        let lines = self.matrix.full_lines();
+        self.matrix.clear_line(&lines);
+    }
+}
+
+
+/// Speaks TBP on stdin/stdout on behalf of `bot`.
+pub fn run(bot: Bot) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    let mut send = |message: Json| -> io::Result<()> {
+        writeln!(stdout, "{message}")?;
+        stdout.flush()
+    };
+
+    send(Json::object([
+        ("type", "info".into()),
+        ("name", "tetris".into()),
+        ("version", env!("CARGO_PKG_VERSION").into()),
+        ("author", "tetris contributors".into()),
+        ("features", Json::Array(Vec::new())),
+    ]))?;
+
+    let mut state: Option<State> = None;
+    for line in io::stdin().lock().lines() {
+        let Ok(message_json) = Json::parse(&line?) else {
+            continue;
+        };
+        match message_json.get("type").and_then(Json::as_str) {
+            Some("rules") => send(message("ready"))?,
+            Some("start") => state = State::start(&message_json),
+            Some("stop") => state = None,
+            Some("suggest") => {
+                let moves = state.as_ref().map(|state| state.suggest(&bot)).unwrap_or_default();
+                send(Json::object([("type", "suggestion".into()), ("moves", Json::Array(moves))]))?;
+            },
+            Some("play") => {
+                let piece = message_json.get("move").and_then(|m| m.get("location")).and_then(|location| parse_location(location, &[]));
+                if let (Some(state), Some(piece)) = (state.as_mut(), piece) {
+                    state.play(&piece);
+                }
+            },
+            Some("new_piece") => {
+                let kind = message_json.get("piece").and_then(|piece| parse_kind(piece, &[]));
+                if let (Some(state), Some(kind)) = (state.as_mut(), kind) {
+                    state.queue.push_back(kind);
+                }
+            },
+            Some("quit") => break,
+            _ => {},
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,133 @@
+//! Tetris Bot Protocol (TBP): newline separated JSON messages over a bot's stdin/stdout.
+//! `frontend` runs games against external bots, `host` exposes our own bot.
+
+use crate::engine::{Color, Coordinate, Matrix, Offset, piece::{Piece, Kind as PieceKind, Rotation}};
+use crate::json::Json;
+
+pub mod frontend;
+pub mod host;
+
+const BOARD_HEIGHT: usize = 40;
+
+
+pub(crate) fn kind_name(kind: PieceKind) -> &'static str {
+    match kind {
+        PieceKind::Custom(shape) => &shape.name,
+        PieceKind::O => "O",
+        PieceKind::I => "I",
+        PieceKind::T => "T",
+        PieceKind::L => "L",
+        PieceKind::J => "J",
+        PieceKind::S => "S",
+        PieceKind::Z => "Z",
+    }
+}
+
+/// `custom` lists the kinds from outside the tetrominoes names may stand for,
+/// empty for TBP itself.
+pub(crate) fn parse_kind(json: &Json, custom: &[PieceKind]) -> Option<PieceKind> {
+    let name = json.as_str()?;
+    custom.iter().copied().chain(PieceKind::ALL).find(|kind| kind_name(*kind) == name)
+}
+
+fn cell_name(color: Color, custom: &[PieceKind]) -> &'static str {
+    custom.iter().copied().chain(PieceKind::ALL)
+        .find(|kind| kind.color() == color)
+        .map_or("G", kind_name)
+}
+
+fn orientation_name(rotation: Rotation) -> &'static str {
+    match rotation {
+        Rotation::N => "north",
+        Rotation::E => "east",
+        Rotation::S => "south",
+        Rotation::W => "west",
+    }
+}
+
+/// Board rows bottom first, padded to the 40 rows TBP expects.
+/// Cells are named after the kinds of their color, `custom` ones first.
+pub(crate) fn board(matrix: &Matrix, custom: &[PieceKind]) -> Json {
+    (0..BOARD_HEIGHT)
+        .map(|y| {
+            (0..Matrix::WIDTH)
+                .map(|x| match y < Matrix::HEIGHT {
+                    true => matrix[Coordinate::new(x, y)].map(|color| cell_name(color, custom)),
+                    false => None,
+                })
+                .collect::<Json>()
+        })
+        .collect()
+}
+
+pub(crate) fn parse_board(json: &Json, custom: &[PieceKind]) -> Option<Matrix> {
+    let mut matrix = Matrix::blank();
+    for (y, row) in json.as_array()?.iter().enumerate() {
+        for (x, cell) in row.as_array()?.iter().enumerate() {
+            if *cell == Json::Null {
+                continue;
+            }
+            if y >= Matrix::HEIGHT || x >= Matrix::WIDTH {
+                return None;
+            }
+            let color = parse_kind(cell, custom).map_or(Color::Garbage, |kind| kind.color());
+            matrix[Coordinate::new(x, y)] = Some(color);
+        }
+    }
+    Some(matrix)
+}
+
+pub(crate) fn location(piece: &Piece) -> Json {
+    let center = piece.srs_center().unwrap();
+    Json::object([
+        ("type", kind_name(piece.kind).into()),
+        ("orientation", orientation_name(piece.rotation).into()),
+        ("x", (center.x as i64).into()),
+        ("y", (center.y as i64).into()),
+    ])
+}
+
+pub(crate) fn parse_location(json: &Json, custom: &[PieceKind]) -> Option<Piece> {
+    let kind = parse_kind(json.get("type")?, custom)?;
+    let orientation = json.get("orientation")?.as_str()?;
+    let rotation = Rotation::ALL.into_iter().find(|rotation| orientation_name(*rotation) == orientation)?;
+    let center = Offset::new(json.get("x")?.as_i64()? as isize, json.get("y")?.as_i64()? as isize);
+    Piece::from_srs_center(kind, rotation, center)
+}
+
+fn same_cells(a: &Piece, b: &Piece) -> bool {
+    let sorted = |piece: &Piece| piece.cells().map(|mut cells| {
+        cells.sort_by_key(|coord| (coord.y, coord.x));
+        cells
+    });
+    sorted(a).is_some() && sorted(a) == sorted(b)
+}
+
+fn message(kind: &str) -> Json {
+    Json::object([("type", kind.into())])
+}
+
+fn move_json(piece: &Piece) -> Json {
+    Json::object([("location", location(piece)), ("spin", "none".into())])
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn locations_round_trip() {
+        for kind in PieceKind::ALL {
+            for rotation in Rotation::ALL {
+                let piece = Piece { kind, rotation, position: Offset::new(3, 5), big: false };
+                let parsed = parse_location(&location(&piece), &[]).unwrap();
+                assert!(same_cells(&piece, &parsed), "{piece:?} came back as {parsed:?}");
+            }
+        }
+
+        let t = Piece::spawned(PieceKind::T);
+        assert_eq!(location(&t).get("x").and_then(Json::as_i64), Some(4));
+        assert_eq!(location(&t).get("y").and_then(Json::as_i64), Some(Matrix::HEIGHT as i64 - 2));
+    }
+}
@@ -0,0 +1,149 @@
+use std::{fmt, str::FromStr};
+use crate::engine::{LineClear, Spin};
+
+/// Garbage lines sent for each kind of clear. Values add up, so a
+/// back to back T-spin double in a combo sends `spins[2] + back_to_back + combo[n]`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AttackTable {
+    /// Clearing 1 to 4 lines without a spin.
+    pub clears: [usize; 4],
+    /// T-spin minis clearing 0 to 2 lines.
+    pub minis: [usize; 3],
+    /// T-spins clearing 0 to 3 lines.
+    pub spins: [usize; 4],
+    pub back_to_back: usize,
+    /// Bonus by combo count, the last entry repeats for longer combos.
+    pub combo: Vec<usize>,
+    pub perfect_clear: usize,
+}
+
+impl Default for AttackTable {
+    fn default() -> Self {
+        AttackTable {
+            clears: [0, 1, 2, 4],
+            minis: [0, 0, 1],
+            spins: [0, 2, 4, 6],
+            back_to_back: 1,
+            combo: vec![0, 0, 1, 1, 1, 2, 2, 3, 3, 4, 4, 4, 5],
+            perfect_clear: 10,
+        }
+    }
+}
+
+impl AttackTable {
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self, String> {
+        std::fs::read_to_string(path)
+            .map_err(|error| error.to_string())?
+            .parse()
+    }
+
+    pub fn attack(&self, clear: &LineClear) -> usize {
+        let base = match (clear.spin, clear.lines) {
+            (None, 0) => return 0,
+            (None, lines) => self.clears[lines.min(4) - 1],
+            (Some(Spin::Mini), lines) => self.minis[lines.min(2)],
+            (Some(Spin::Full), lines) => self.spins[lines.min(3)],
+        };
+        if clear.lines == 0 {
+            return base;
+        }
+
+        let combo = self.combo.get(clear.combo).or(self.combo.last()).copied().unwrap_or(0);
+        let back_to_back = if clear.back_to_back { self.back_to_back } else { 0 };
+        let perfect_clear = if clear.perfect_clear { self.perfect_clear } else { 0 };
+        base + combo + back_to_back + perfect_clear
+    }
+
+    fn fields(&mut self) -> [(&'static str, &mut usize); 13] {
+        let [single, double, triple, tetris] = &mut self.clears;
+        let [mini, mini_single, mini_double] = &mut self.minis;
+        let [spin, spin_single, spin_double, spin_triple] = &mut self.spins;
+        [
+            ("single", single),
+            ("double", double),
+            ("triple", triple),
+            ("tetris", tetris),
+            ("tspin_mini", mini),
+            ("tspin_mini_single", mini_single),
+            ("tspin_mini_double", mini_double),
+            ("tspin", spin),
+            ("tspin_single", spin_single),
+            ("tspin_double", spin_double),
+            ("tspin_triple", spin_triple),
+            ("back_to_back", &mut self.back_to_back),
+            ("perfect_clear", &mut self.perfect_clear),
+        ]
+    }
+}
+
+
+/// Same `name = value` lines as weight files, `combo` takes a space separated list.
+impl fmt::Display for AttackTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, value) in self.clone().fields() {
+            writeln!(f, "{name} = {value}")?;
+        }
+        let combo = self.combo.iter().map(usize::to_string).collect::<Vec<_>>();
+        writeln!(f, "combo = {}", combo.join(" "))
+    }
+}
+
+/// Entries missing from the text keep their default value.
+impl FromStr for AttackTable {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut table = AttackTable::default();
+
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (name, value) = line.split_once('=').ok_or_else(|| format!("expected `name = value`, got `{line}`"))?;
+            let (name, value) = (name.trim(), value.trim());
+            let bad_value = || format!("bad value for `{name}`");
+
+            if name == "combo" {
+                table.combo = value.split_whitespace()
+                    .map(|bonus| bonus.parse().map_err(|_| bad_value()))
+                    .collect::<Result<_, _>>()?;
+                continue;
+            }
+            let mut fields = table.fields();
+            let (_, field) = fields.iter_mut()
+                .find(|(known, _)| *known == name)
+                .ok_or_else(|| format!("unknown attack `{name}`"))?;
+            **field = value.parse().map_err(|_| bad_value())?;
+        }
+        Ok(table)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn attack_values() {
+        let table = AttackTable::default();
+        let clear = |lines, spin| LineClear { lines, spin, ..LineClear::default() };
+
+        assert_eq!(table.attack(&clear(1, None)), 0);
+        assert_eq!(table.attack(&clear(4, None)), 4);
+        assert_eq!(table.attack(&clear(2, Some(Spin::Full))), 4);
+        assert_eq!(table.attack(&LineClear { back_to_back: true, combo: 20, ..clear(4, None) }), 10);
+        assert_eq!(table.attack(&LineClear { perfect_clear: true, ..clear(2, None) }), 11);
+    }
+
+    #[test]
+    fn table_file_round_trip() {
+        let table = AttackTable { spins: [1, 2, 3, 4], combo: vec![0, 1], ..AttackTable::default() };
+        assert_eq!(table.to_string().parse::<AttackTable>(), Ok(table));
+
+        let partial = "tetris = 5 # harder\ncombo = 0 2\n".parse::<AttackTable>().unwrap();
+        assert_eq!(partial, AttackTable { clears: [0, 1, 2, 5], combo: vec![0, 2], ..AttackTable::default() });
+        assert!("quad = 4".parse::<AttackTable>().is_err());
+    }
+}
@@ -0,0 +1,191 @@
+use std::{collections::VecDeque, time::Duration};
+use rand::{prelude::StdRng, Rng, SeedableRng};
+use crate::engine::{Engine, LineClear, Matrix};
+
+pub use self::attack::AttackTable;
+
+mod attack;
+
+
+#[derive(Clone, Debug)]
+pub struct Settings {
+    pub attack: AttackTable,
+    /// How long sent garbage waits before it can enter the field.
+    pub entry_delay: Duration,
+    /// Chance of the hole moving between rows of one attack.
+    pub messiness: f64,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            attack: AttackTable::default(),
+            entry_delay: Duration::from_millis(500),
+            messiness: 0.0,
+        }
+    }
+}
+
+
+/// One attack waiting in a garbage queue.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Garbage {
+    lines: usize,
+    hole: usize,
+    /// Time left before it may enter.
+    delay: Duration,
+}
+
+#[derive(Clone)]
+pub struct Player {
+    pub engine: Engine,
+    pending: VecDeque<Garbage>,
+    pub sent: usize,
+}
+
+impl Player {
+    /// Incoming garbage lines, entered or not.
+    pub fn pending(&self) -> usize {
+        self.pending.iter().map(|garbage| garbage.lines).sum()
+    }
+
+    /// Uses `attack` to cancel queued garbage, oldest first, returns what is left.
+    fn cancel(&mut self, mut attack: usize) -> usize {
+        while let Some(garbage) = self.pending.front_mut().filter(|_| attack > 0) {
+            let cancelled = garbage.lines.min(attack);
+            garbage.lines -= cancelled;
+            attack -= cancelled;
+            if garbage.lines == 0 {
+                self.pending.pop_front();
+            }
+        }
+        attack
+    }
+
+    /// Pushes in every attack whose entry delay has run out.
+    fn receive(&mut self, messiness: f64) {
+        while let Some(garbage) = self.pending.front().filter(|garbage| garbage.delay.is_zero()) {
+            let Garbage { lines, hole, .. } = *garbage;
+            self.pending.pop_front();
+            if self.engine.add_garbage(lines, hole, messiness).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+
+/// Several engines sending garbage to each other, independent of any window
+/// so bots and network games can drive it too.
+#[derive(Clone)]
+pub struct Versus {
+    pub players: Vec<Player>,
+    pub settings: Settings,
+    rng: StdRng,
+}
+
+impl Versus {
+    /// Every player gets the same piece sequence from `seed`.
+    pub fn new(count: usize, seed: u64, settings: Settings) -> Self {
+        let players = (0..count)
+            .map(|_| Player { engine: Engine::with_seed(seed), pending: VecDeque::new(), sent: 0 })
+            .collect();
+        Versus { players, settings, rng: StdRng::seed_from_u64(seed) }
+    }
+
+    /// Counts down garbage entry delays.
+    pub fn update(&mut self, elapsed: Duration) {
+        for garbage in self.players.iter_mut().flat_map(|player| &mut player.pending) {
+            garbage.delay = garbage.delay.saturating_sub(elapsed);
+        }
+    }
+
+    /// Runs `line_clear` for player `index` and settles the garbage: an attack
+    /// cancels incoming garbage first and the rest goes to the next player still
+    /// in the game. A piece that clears nothing lets ready garbage in.
+    pub fn line_clear(&mut self, index: usize) -> Option<LineClear> {
+        let clear = self.players[index].engine.line_clear(|_| ())?;
+        let player = &mut self.players[index];
+
+        let attack = player.cancel(self.settings.attack.attack(&clear));
+        if clear.lines == 0 {
+            player.receive(self.settings.messiness);
+        }
+
+        if let Some(target) = self.target(index).filter(|_| attack > 0) {
+            let hole = self.rng.gen_range(0..Matrix::WIDTH);
+            self.players[index].sent += attack;
+            self.players[target].pending.push_back(Garbage {
+                lines: attack,
+                hole,
+                delay: self.settings.entry_delay,
+            });
+        }
+        Some(clear)
+    }
+
+    fn target(&self, index: usize) -> Option<usize> {
+        (1..self.players.len())
+            .map(|step| (index + step) % self.players.len())
+            .find(|&other| !self.players[other].engine.is_topped_out())
+    }
+
+    /// The last player standing, once everyone else topped out.
+    pub fn winner(&self) -> Option<usize> {
+        let mut alive = (0..self.players.len()).filter(|&index| !self.players[index].engine.is_topped_out());
+        match (alive.next(), alive.next()) {
+            (Some(winner), None) if self.players.len() > 1 => Some(winner),
+            _ => None,
+        }
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.players.iter().filter(|player| !player.engine.is_topped_out()).count() <= 1
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::engine::piece::{Kind as PieceKind, RotateKind};
+    use crate::engine::Offset;
+
+    /// Drops a vertical I into the rightmost column of player `index`.
+    fn drop_i(versus: &mut Versus, index: usize) -> Option<LineClear> {
+        let engine = &mut versus.players[index].engine;
+        engine.db_test_cursor(PieceKind::I, Offset::new(7, 10));
+        engine.rotate_cursor(RotateKind::Clockwise).unwrap();
+        engine.hard_drop();
+        versus.line_clear(index)
+    }
+
+    #[test]
+    fn garbage_cancels_and_waits() {
+        let settings = Settings { entry_delay: Duration::from_secs(1), ..Settings::default() };
+        let mut versus = Versus::new(2, 3, settings);
+        let tetris_ready = "
+            GGGGGGGGG.
+            GGGGGGGGG.
+            GGGGGGGGG.
+            GGGGGGGGG.
+            GGGGGGGG.G
+        ".parse::<Matrix>().unwrap();
+        versus.players[0].engine = Engine::with_matrix(tetris_ready);
+        versus.players[1].engine = Engine::with_matrix(tetris_ready);
+
+        assert_eq!(drop_i(&mut versus, 0).map(|clear| clear.lines), Some(4));
+        assert_eq!(versus.players[1].pending(), 4);
+
+        versus.players[1].engine.db_test_cursor(PieceKind::O, Offset::new(-1, 5));
+        versus.players[1].engine.hard_drop();
+        versus.line_clear(1);
+        assert_eq!(versus.players[1].pending(), 4, "garbage entered before its delay");
+
+        versus.update(Duration::from_secs(1));
+        drop_i(&mut versus, 1);
+        assert_eq!(versus.players[1].pending(), 0, "tetris should cancel the garbage");
+        assert_eq!(versus.players[0].pending(), 0);
+        assert_eq!(versus.winner(), None);
+    }
+}